@@ -6,6 +6,7 @@ pub use pulldown_cmark;
 pub use pulldown_cmark_escape;
 pub use reqwest;
 pub use serde_json;
+pub use serde_yaml;
 pub use slug;
 pub use toml;
 pub use walkdir;
@@ -16,4 +17,7 @@ pub use tera;
 pub use gh_emoji;
 pub use syntect;
 pub use once_cell;
+pub use mime_guess;
+pub use percent_encoding;
+pub use grass;
 
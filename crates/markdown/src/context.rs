@@ -36,10 +36,10 @@ impl<'a> RenderContext<'a> {
 
     
 
-    /// Creates a new RenderContext with default values
-    pub fn from_config(config: &'a Config) -> RenderContext<'a>{
+    /// Creates a RenderContext with default values, rendering with the templates of the site
+    pub fn from_config(config: &'a Config, tera: &'a Tera) -> RenderContext<'a>{
         Self {
-            tera: Cow::Owned(Tera::default()),
+            tera: Cow::Borrowed(tera),
             tera_context: TeraContext::new(),
             config,
             current_page_path: None,
@@ -3,6 +3,7 @@ pub mod markdown;
 pub mod codeblock;
 pub mod fence;
 pub mod highlight;
+pub mod render;
 
 pub use context::RenderContext;
 pub use markdown::{markdown_to_html, Rendered};
@@ -2,30 +2,35 @@
 
 use libs::{pulldown_cmark::LinkType};
 use libs::gh_emoji::Replacer as EmojiReplacer;
+use libs::regex::Regex;
 use once_cell::sync::Lazy;
 use pulldown_cmark::CowStr;
 use pulldown_cmark as cmark;
 use pulldown_cmark_escape::escape_html;
 use anyhow::{Error, Result};
+use std::collections::HashMap;
 use std::fmt::Write;
+use std::mem::discriminant;
 use std::vec;
 
 use pulldown_cmark::{Event, Options, Parser, Tag};
 use crate::fence::FenceSettings;
 use crate::{codeblock::CodeBlock, context::RenderContext};
-use utils::{content::Heading, net::is_external_link};
+use utils::{content::{make_table_of_content, Heading, InternalLink}, net::is_external_link};
 
 static EMOJI_REPLACER: Lazy<EmojiReplacer> = Lazy::new(|| EmojiReplacer::new());
 const CONTINUE_READING: &str = "<span id=\"continue-reading\"></span>";
+static MORE_DIVIDER_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"<!--\s*more\s*-->").unwrap());
 pub const SHORTCODE_PLACEHOLDER: &str = "@@GENETICS_SHORTCODE_PLACEHOLDER@@";
 
 #[derive(Debug)]
 pub struct Rendered {
     pub body: String,
     pub summary: Option<String>,
-   // pub toc: Vec<HeadingStruct>,
+    /// Headings of the content, nested by level
+    pub toc: Vec<Heading>,
     /// Links to site-local pages: relative path plus optional anchor target.
-    pub internal_links: Vec<(String, Option<String>)>,
+    pub internal_links: Vec<InternalLink>,
     /// Outgoing links to external webpages (i.e. HTTP(S) targets).
     pub external_links: Vec<String>,
 }
@@ -96,18 +101,24 @@ fn get_text(parser_slice: &[Event]) -> String {
 fn link_fixer(
     link_type: LinkType, 
     link: &str, 
+    line: usize,
     context: &RenderContext, 
-    internal_links: &mut Vec<(String, Option<String>)>,
+    internal_links: &mut Vec<InternalLink>,
     external_links: &mut Vec<String>,
 ) -> Result<String>{
     if link_type == LinkType::Email {
         return Ok(link.to_string());
     }
 
-    let result = if link.starts_with("@/") {
-        // Handle internal links starting with @/
-        // For now, just return the link as is
-        link.to_string()
+    let result = if let Some(internal) = link.strip_prefix("@/") {
+        let resolved = resolve_internal_link(internal, &context.permalinks);
+        internal_links.push(InternalLink {
+            relative_path: resolved.relative_path,
+            anchor: resolved.anchor,
+            line,
+        });
+        // a missing target is reported with the other broken links once everything is rendered
+        resolved.permalink.unwrap_or_else(|| link.to_string())
     }
     else if is_external_link(link){
         external_links.push(link.to_owned());
@@ -119,7 +130,11 @@ fn link_fixer(
     else if let Some(stripped_link) = link.strip_prefix('#') {
         // local anchor without the internal zola path
         if let Some(current_path) = context.current_page_path {
-            internal_links.push((current_path.to_owned(), Some(stripped_link.to_owned())));
+            internal_links.push(InternalLink {
+                relative_path: current_path.to_owned(),
+                anchor: Some(stripped_link.to_owned()),
+                line,
+            });
             format!("{}{}", context.current_page_permalink, &link)
         } else {
             link.to_string()
@@ -133,6 +148,36 @@ fn link_fixer(
 }
 
 
+/// Line of a byte offset in `content`, starting at 1
+fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset].matches('\n').count() + 1
+}
+
+/// An `@/` link resolved against the permalinks of the site
+#[derive(Debug, PartialEq)]
+struct ResolvedInternalLink {
+    /// Path of the target relative to the content directory
+    relative_path: String,
+    /// Permalink of the target, anchor included, `None` when no file has that path
+    permalink: Option<String>,
+    anchor: Option<String>,
+}
+
+/// Resolves `blog/post.md#anchor` (the part after `@/`) to the permalink of the target file
+fn resolve_internal_link(link: &str, permalinks: &HashMap<String, String>) -> ResolvedInternalLink {
+    let (path, anchor) = match link.split_once('#') {
+        Some((path, anchor)) => (path, Some(anchor.to_string())),
+        None => (link, None),
+    };
+
+    let permalink = permalinks.get(path).map(|permalink| match &anchor {
+        Some(anchor) => format!("{}#{}", permalink, anchor),
+        None => permalink.clone(),
+    });
+
+    ResolvedInternalLink { relative_path: path.to_string(), permalink, anchor }
+}
+
 /// Returns a unique anchor for a heading
 fn get_anchor(anchors: &[String], name: String, level: u16) -> String {
     if level == 0 && !anchors.contains(&name) {
@@ -180,7 +225,7 @@ pub fn markdown_to_html(content: &str, context: &RenderContext) -> Result<Render
         .or_else(|| context.tera_context.get("section"))
         .map(|x| x.as_object().unwrap().get("relative_path").unwrap().as_str().unwrap());
     let mut html = String::with_capacity(content.len());
-    let summary = None;
+    let mut summary = None;
     // Set while parsing
     let mut error = None;
     let inside_attribute = false;
@@ -190,6 +235,7 @@ pub fn markdown_to_html(content: &str, context: &RenderContext) -> Result<Render
     let mut code_block: Option<CodeBlock> = None; 
 
     let mut stop_next_end_p = false;
+    let mut has_summary = false;
     let mut headings: Vec<Heading> = Vec::new();
 
 
@@ -207,7 +253,7 @@ pub fn markdown_to_html(content: &str, context: &RenderContext) -> Result<Render
         let mut accumulated_blocks = String::new(); 
     
         let mut events = Vec::new();
-        for (event, _) in Parser::new_ext(content, opts).into_offset_iter() {
+        for (event, range) in Parser::new_ext(content, opts).into_offset_iter() {
             match event {
                 Event::Text(text) => {
                     if let Some(ref mut _code_block) = code_block {
@@ -289,6 +335,7 @@ pub fn markdown_to_html(content: &str, context: &RenderContext) -> Result<Render
                         let fixed_link = match link_fixer(
                             link_type, 
                             &dest_url.to_string(), 
+                            line_of(content, range.start),
                             context, 
                             &mut internal_links, 
                             &mut external_links,
@@ -301,21 +348,17 @@ pub fn markdown_to_html(content: &str, context: &RenderContext) -> Result<Render
                             }
                         };
 
-                        events.push(
-                            if is_external_link(&dest_url) {
-                                let mut escaped = String::new(); 
-                                pulldown_cmark_escape::escape_href(&mut escaped, &dest_url)
-                                    .expect("Could not write to buffer");
-                                Event::Html(format!("<a href='{}'>{}</a>", escaped, title).into())
-                            } else {
-                                Event::Start(Tag::Link(link_type, fixed_link.into(), title.into()))
-                            }
-                        );
+                        events.push(Event::Start(Tag::Link(link_type, fixed_link.into(), title)));
                     }
                 }
-                Event::Start(Tag::Paragraph) => {
-                    stop_next_end_p = true; 
-                    events.push(event);
+                Event::Html(text) if !has_summary && MORE_DIVIDER_REGEX.is_match(&text) => {
+                    has_summary = true;
+                    // `<!-- more -->` alone in its paragraph shouldn't leave an empty one behind
+                    if matches!(events.last(), Some(Event::Start(Tag::Paragraph))) {
+                        events.pop();
+                        stop_next_end_p = true;
+                    }
+                    events.push(Event::Html(CONTINUE_READING.into()));
                 },
                 Event::End(Tag::Paragraph) => {
                     events.push(if stop_next_end_p {
@@ -379,32 +422,37 @@ pub fn markdown_to_html(content: &str, context: &RenderContext) -> Result<Render
 
         let continue_reading = events
             .iter()
-            .position(|e| matches!(e, Event::Html(CowStr::Borrowed(CONTINUE_READING))))
-            .unwrap_or(events.len());
-
-
-        // This line creates a new empty vector to track HTML tags
-        let mut tags: Vec<Tag> = Vec::new();
-        for event in &events[..continue_reading] {
-            match event {
-                Event::Html(_) => {},
-                Event::Start(tag) => tags.push(tag.clone()),
-                Event::End(end_tag) => {
-                  tags.truncate(tags.iter().rposition(|x|*x == *end_tag).unwrap_or(0));
-                }, 
-                _ => {}
+            .position(|e| matches!(e, Event::Html(CowStr::Borrowed(CONTINUE_READING))));
+
+        if let Some(continue_reading) = continue_reading {
+            // Tags still open at the marker, closed at the end of the summary
+            let mut tags: Vec<Tag> = Vec::new();
+            for event in &events[..continue_reading] {
+                match event {
+                    Event::Start(tag) => tags.push(tag.clone()),
+                    Event::End(end_tag) => {
+                        // links are ended with their original destination, so only the kind of tag is compared
+                        let start = tags.iter().rposition(|x| discriminant(x) == discriminant(end_tag));
+                        if let Some(start) = start {
+                            tags.truncate(start);
+                        }
+                    },
+                    _ => {}
+                }
             }
-        }
 
-        //let parser = Parser::new_ext(content, opts);
-        //events.extend(parser);
-        // The `into_iter()` method consumes the `events` vector and returns an iterator over its elements. The `mut` keyword indicates that the iterator can mutate the elements.
-        // By assigning the iterator to the `events` variable, we can use the iterator to iterate over the elements of the vector one by one.
-        // The `mut` keyword is necessary because the `events` vector is being consumed and we want to be able to modify its elements if needed.
-        let mut events = events.into_iter();
+            let mut summary_html = String::new();
+            cmark::html::push_html(
+                &mut summary_html,
+                events[..continue_reading]
+                    .iter()
+                    .cloned()
+                    .chain(tags.into_iter().rev().map(Event::End)),
+            );
+            summary = Some(summary_html);
+        }
 
-        cmark::html::push_html(&mut html, events.by_ref().take(continue_reading));
-       // events.for_each(drop);
+        cmark::html::push_html(&mut html, events.into_iter());
     }
 
     if let Some(e) = error {
@@ -414,7 +462,7 @@ pub fn markdown_to_html(content: &str, context: &RenderContext) -> Result<Render
         Ok(Rendered {
             summary, 
             body: html, 
-            // toc, 
+            toc: make_table_of_content(headings), 
             internal_links, 
             external_links
         })
@@ -509,6 +557,99 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_internal_links_resolve_to_permalinks() -> Result<()> {
+        let mut context = create_test_context();
+        let mut permalinks = HashMap::new();
+        permalinks.insert("blog/post.md".to_string(), "http://localhost:8080/blog/post/".to_string());
+        context.permalinks = std::borrow::Cow::Owned(permalinks);
+
+        let result = markdown_to_html("[Post](@/blog/post.md#intro)", &context)?;
+
+        assert!(result.body.contains(r#"<a href="http://localhost:8080/blog/post/#intro">Post</a>"#));
+        assert_eq!(result.internal_links, vec![InternalLink {
+            relative_path: "blog/post.md".to_string(),
+            anchor: Some("intro".to_string()),
+            line: 1,
+        }]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_internal_link_is_recorded() -> Result<()> {
+        let context = create_test_context();
+        let result = markdown_to_html("Intro\n\n[Post](@/blog/missing.md)", &context)?;
+
+        assert!(result.body.contains(r#"<a href="@/blog/missing.md">Post</a>"#));
+        assert_eq!(result.internal_links, vec![InternalLink {
+            relative_path: "blog/missing.md".to_string(),
+            anchor: None,
+            line: 3,
+        }]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_internal_links_record_their_line() -> Result<()> {
+        let context = create_test_context();
+        let result = markdown_to_html("# Intro\n\nSome text\nand [a link](#getting-started)", &context)?;
+
+        assert!(result.body.contains(r#"<h1 id="Intro">"#));
+        assert_eq!(result.internal_links, vec![InternalLink {
+            relative_path: "test.md".to_string(),
+            anchor: Some("getting-started".to_string()),
+            line: 4,
+        }]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_toc_is_nested() -> Result<()> {
+        let context = create_test_context();
+        let result = markdown_to_html("## Install\n#### From source\n## Usage", &context)?;
+
+        assert_eq!(result.toc.len(), 2);
+        assert_eq!(result.toc[0].id, "Install");
+        assert_eq!(result.toc[0].children[0].title, "From source");
+        assert_eq!(result.toc[0].children[0].permalink, "/test/#From source");
+        Ok(())
+    }
+
+    #[test]
+    fn test_summary_stops_at_more_marker() -> Result<()> {
+        let context = create_test_context();
+        let markdown = "First paragraph.\n\n<!-- more -->\n\nSecond paragraph.";
+        let result = markdown_to_html(markdown, &context)?;
+
+        assert_eq!(result.summary.as_deref(), Some("<p>First paragraph.</p>\n"));
+        assert!(result.body.contains(CONTINUE_READING));
+        assert!(result.body.contains("<p>Second paragraph.</p>"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_summary_closes_open_tags() -> Result<()> {
+        let context = create_test_context();
+        let markdown = "- *one <!-- more --> two*\n- three";
+        let result = markdown_to_html(markdown, &context)?;
+
+        let summary = result.summary.unwrap();
+        assert!(summary.starts_with("<ul>\n<li><em>one "));
+        assert!(summary.ends_with("</em></li>\n</ul>\n"));
+        assert!(!summary.contains("two"));
+        assert!(result.body.contains("three"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_summary_without_marker() -> Result<()> {
+        let context = create_test_context();
+        let result = markdown_to_html("Just one paragraph.", &context)?;
+        assert_eq!(result.summary, None);
+        assert!(result.body.contains("<p>Just one paragraph.</p>"));
+        Ok(())
+    }
+
     #[test]
     fn test_lists() -> Result<()> {
         let context = create_test_context();
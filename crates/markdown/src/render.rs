@@ -0,0 +1,129 @@
+use std::{borrow::Cow, collections::HashMap, path::Path};
+use errors::{Context, Error, Result};
+use config::Config;
+use tera::{Context as TeraContext, Tera};
+use utils::{page::Page, section::Section, site::{Config as SiteConfig, Site}};
+
+use crate::{markdown_to_html, RenderContext};
+
+/// Renders the markdown content of every page and section of the site to HTML
+///
+/// Once everything is rendered, internal links are checked against the rendered headings.
+pub fn render_markdown(site: &mut Site) -> Result<()> {
+    let config = rendering_config(&site.config);
+
+    for page in site.pages.values_mut() {
+        render_page(page, &site.tera, &config, &site.permalinks)?;
+    }
+
+    for section in site.sections.values_mut() {
+        render_section(section, &site.tera, &config, &site.permalinks)?;
+    }
+
+    site.check_internal_links()
+}
+
+/// Renders the markdown of the whole site like [`render_markdown`], going on after a
+/// failure to return every error found
+pub fn check_markdown(site: &mut Site) -> Vec<Error> {
+    let config = rendering_config(&site.config);
+
+    let mut errors = Vec::new();
+    for page in site.pages.values_mut() {
+        if let Err(err) = render_page(page, &site.tera, &config, &site.permalinks) {
+            errors.push(err);
+        }
+    }
+    for section in site.sections.values_mut() {
+        if let Err(err) = render_section(section, &site.tera, &config, &site.permalinks) {
+            errors.push(err);
+        }
+    }
+
+    // pages come in no particular order, the report shouldn't change from a run to the next
+    errors.sort_by_key(|err| format!("{:#}", err));
+
+    if let Err(err) = site.check_internal_links() {
+        errors.push(err);
+    }
+    errors
+}
+
+/// Renders the markdown content of a single page of the site, see [`render_markdown`]
+pub fn render_page_markdown(site: &mut Site, path: &Path) -> Result<()> {
+    let config = rendering_config(&site.config);
+    let page = site
+        .pages
+        .get_mut(path)
+        .with_context(|| format!("{} is not a page of the site", path.display()))?;
+
+    render_page(page, &site.tera, &config, &site.permalinks)?;
+    site.check_internal_links()
+}
+
+/// The config markdown is rendered with, from the config loaded from `config.toml`
+fn rendering_config(site_config: &SiteConfig) -> Config {
+    Config {
+        base_url: site_config.base_url.clone(),
+        title: site_config.title.clone(),
+        description: site_config.description.clone(),
+        ..Config::default()
+    }
+}
+
+fn render_section(
+    section: &mut Section,
+    tera: &Tera,
+    config: &Config,
+    permalinks: &HashMap<String, String>,
+) -> Result<()> {
+    let mut tera_context = TeraContext::new();
+    tera_context.insert("section", &section.serialize_basic());
+
+    let context = RenderContext::new(
+        tera,
+        config,
+        tera_context,
+        Some(&section.relative_path),
+        &section.permalink,
+        Cow::Borrowed(permalinks),
+    );
+
+    let rendered = markdown_to_html(&section.raw_content, &context)
+        .with_context(|| format!("Failed to render markdown of {}", section.file))?;
+
+    section.content = rendered.body;
+    section.toc = rendered.toc;
+    section.internal_links = rendered.internal_links;
+    section.external_links = rendered.external_links;
+    Ok(())
+}
+
+fn render_page(
+    page: &mut Page,
+    tera: &Tera,
+    config: &Config,
+    permalinks: &HashMap<String, String>,
+) -> Result<()> {
+    let mut tera_context = TeraContext::new();
+    tera_context.insert("page", &page.serialize());
+
+    let context = RenderContext::new(
+        tera,
+        config,
+        tera_context,
+        Some(&page.relative_path),
+        &page.permalink,
+        Cow::Borrowed(permalinks),
+    );
+
+    let rendered = markdown_to_html(&page.raw_content, &context)
+        .with_context(|| format!("Failed to render markdown of {}", page.file))?;
+
+    page.content = rendered.body;
+    page.summary = rendered.summary;
+    page.toc = rendered.toc;
+    page.internal_links = rendered.internal_links;
+    page.external_links = rendered.external_links;
+    Ok(())
+}
@@ -0,0 +1,84 @@
+(function () {
+  var port = __LIVERELOAD_PORT__;
+  var protocol = "http://livereload.com/protocols/official-7";
+
+  function reloadStylesheets(path) {
+    var links = document.querySelectorAll('link[rel="stylesheet"]');
+    var swapped = false;
+    for (var i = 0; i < links.length; i++) {
+      var url = new URL(links[i].href);
+      if (url.pathname === path || url.pathname.endsWith(path)) {
+        url.searchParams.set("livereload", Date.now());
+        links[i].href = url.toString();
+        swapped = true;
+      }
+    }
+    return swapped;
+  }
+
+  var overlayId = "genetics-error-overlay";
+
+  function showError(message) {
+    var overlay = document.getElementById(overlayId);
+    if (!overlay) {
+      overlay = document.createElement("div");
+      overlay.id = overlayId;
+      overlay.style.cssText =
+        "position:fixed;inset:0;z-index:2147483647;overflow:auto;padding:2em;" +
+        "background:rgba(20,20,20,0.92);color:#f8f8f2;font:14px/1.5 monospace;";
+      document.body.appendChild(overlay);
+    }
+
+    var title = document.createElement("h2");
+    title.style.cssText = "margin:0 0 1em;color:#ff6b6b;font:bold 18px sans-serif;";
+    title.textContent = "Failed to build the site";
+
+    var location = document.createElement("p");
+    location.textContent = message.file
+      ? message.file + (message.line ? ":" + message.line : "")
+      : "";
+
+    var details = document.createElement("pre");
+    details.style.cssText = "white-space:pre-wrap;margin:0;";
+    details.textContent = message.message;
+
+    overlay.replaceChildren(title, location, details);
+  }
+
+  function connect() {
+    var socket = new WebSocket("ws://" + window.location.hostname + ":" + port + "/livereload");
+
+    socket.onopen = function () {
+      socket.send(JSON.stringify({ command: "hello", protocols: [protocol] }));
+    };
+
+    socket.onmessage = function (event) {
+      var message = JSON.parse(event.data);
+      if (message.command === "error") {
+        showError(message);
+        return;
+      }
+      if (message.command !== "reload") {
+        return;
+      }
+      // the site builds again, reloading gets rid of the overlay and shows the fix
+      var overlay = document.getElementById(overlayId);
+      if (overlay) {
+        overlay.remove();
+        window.location.reload();
+        return;
+      }
+      if (message.liveCSS && /\.css$/.test(message.path) && reloadStylesheets(message.path)) {
+        return;
+      }
+      window.location.reload();
+    };
+
+    // the server restarts when the config changes, keep trying until it's back
+    socket.onclose = function () {
+      setTimeout(connect, 1000);
+    };
+  }
+
+  connect();
+})();
@@ -11,6 +11,17 @@ pub struct Heading {
 }
 
 
+/// A link from a content file to a page of the site, possibly to one of its anchors
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct InternalLink {
+    /// Path of the target relative to the content directory
+    pub relative_path: String,
+    pub anchor: Option<String>,
+    /// Line of the link in the markdown it was found in, starting at 1
+    pub line: usize,
+}
+
+
 impl Heading {
     pub fn new(level: u32) -> Heading  {
         Heading {
@@ -24,31 +35,30 @@ impl Heading {
     }
 }
 
-/// Inserts a heading into its parent
+/// Inserts a heading into its parent, or into the deepest of its descendants that can hold it
 /// 
 /// # Arguments
-/// * `possible_content` - A mutable reference to the parent heading
+/// * `possible_parent` - A mutable reference to the parent heading
 /// * `heading` - A reference to the heading to be inserted
 /// 
 /// # Returns
 /// A boolean indicating whether the heading was inserted
-fn insert_into_parent(possible_content: Option<&mut Heading>, heading: &Heading) -> bool {
-    match possible_content  {
+fn insert_into_parent(possible_parent: Option<&mut Heading>, heading: &Heading) -> bool {
+    match possible_parent {
         Some(parent) => {
-            if heading.level <=  parent.level{
-                return false
+            if heading.level <= parent.level {
+                return false;
             }
 
-            if heading.level + 1 == parent.level + 1 {
+            // levels can be skipped (h2 then h4), so the heading goes under the last child
+            // able to hold it and under the parent itself otherwise
+            if !insert_into_parent(parent.children.last_mut(), heading) {
                 parent.children.push(heading.clone());
-                return true; 
             }
 
-            // avoid gooing deeper for now, this operation will have to be recursive operation
-
             true
         },
-        None => { false }
+        None => false,
     }
 }
 
@@ -56,17 +66,70 @@ fn insert_into_parent(possible_content: Option<&mut Heading>, heading: &Heading)
 /// Makes a table of content from a list of headings
 /// 
 /// # Arguments
-/// * `heading` - A vector of headings
+/// * `headings` - The headings in the order they appear in the document
 /// 
 /// # Returns
 /// A vector of headings representing the table of content
-pub fn make_table_of_content(heading : Vec<Heading>) -> Vec<Heading> {
-    let mut toc = vec![]; 
-    for heading in heading {
-        if toc.is_empty() || !insert_into_parent(toc.iter_mut().last(), &heading) {
+pub fn make_table_of_content(headings: Vec<Heading>) -> Vec<Heading> {
+    let mut toc: Vec<Heading> = vec![]; 
+    for heading in headings {
+        if !insert_into_parent(toc.last_mut(), &heading) {
             toc.push(heading);
         }
     }
 
     toc
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heading(level: u32, id: &str) -> Heading {
+        Heading {
+            id: id.to_string(),
+            ..Heading::new(level)
+        }
+    }
+
+    fn ids(toc: &[Heading]) -> Vec<(&str, Vec<&str>)> {
+        toc.iter()
+            .map(|h| (h.id.as_str(), h.children.iter().map(|c| c.id.as_str()).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn test_toc_nests_headings() {
+        let toc = make_table_of_content(vec![
+            heading(1, "a"),
+            heading(2, "b"),
+            heading(3, "c"),
+            heading(3, "d"),
+            heading(2, "e"),
+            heading(1, "f"),
+        ]);
+
+        assert_eq!(ids(&toc), vec![("a", vec!["b", "e"]), ("f", vec![])]);
+        assert_eq!(ids(&toc[0].children), vec![("b", vec!["c", "d"]), ("e", vec![])]);
+    }
+
+    #[test]
+    fn test_toc_handles_skipped_levels() {
+        let toc = make_table_of_content(vec![
+            heading(2, "a"),
+            heading(4, "b"),
+            heading(3, "c"),
+            heading(4, "d"),
+            heading(3, "e"),
+        ]);
+
+        assert_eq!(ids(&toc), vec![("a", vec!["b", "c", "e"])]);
+        assert_eq!(ids(&toc[0].children), vec![("b", vec![]), ("c", vec!["d"]), ("e", vec![])]);
+    }
+
+    #[test]
+    fn test_toc_starting_deeper_than_later_headings() {
+        let toc = make_table_of_content(vec![heading(3, "a"), heading(2, "b"), heading(3, "c")]);
+        assert_eq!(ids(&toc), vec![("a", vec![]), ("b", vec!["c"])]);
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::page::Page;
+
+/// What the entries of a feed contain
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedContent {
+    /// The whole content of the pages
+    #[default]
+    Full,
+    /// The summary of the pages, i.e. what comes before `<!-- more -->`, or their
+    /// whole content when they have none
+    Summary,
+}
+
+/// The pages a feed is made of: the dated ones, most recent first, at most `limit` of them
+pub fn feed_pages<'a>(
+    pages: &'a HashMap<PathBuf, Page>,
+    paths: impl IntoIterator<Item = &'a PathBuf>,
+    limit: Option<usize>,
+) -> Vec<&'a Page> {
+    let mut feed_pages: Vec<&Page> = paths
+        .into_iter()
+        .map(|path| &pages[path])
+        .filter(|page| page.meta.date.is_some())
+        .collect();
+
+    // pages of the same date are sorted by path for feeds to be the same between builds
+    feed_pages.sort_by(|a, b| b.meta.date.cmp(&a.meta.date).then_with(|| a.file.cmp(&b.file)));
+    if let Some(limit) = limit {
+        feed_pages.truncate(limit);
+    }
+    feed_pages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(file: &str, date: Option<&str>) -> (PathBuf, Page) {
+        let mut page = Page { file: file.to_string(), ..Page::default() };
+        page.meta.date = date.map(str::to_string);
+        (PathBuf::from(file), page)
+    }
+
+    #[test]
+    fn test_feed_pages_are_dated_recent_and_limited() {
+        let pages: HashMap<PathBuf, Page> = [
+            page("a.md", Some("2020-01-01")),
+            page("b.md", Some("2023-01-01")),
+            page("c.md", None),
+            page("d.md", Some("2021-06-01")),
+        ]
+        .into_iter()
+        .collect();
+
+        let files = |limit| -> Vec<&str> {
+            feed_pages(&pages, pages.keys(), limit).iter().map(|page| page.file.as_str()).collect()
+        };
+        assert_eq!(files(None), vec!["b.md", "d.md", "a.md"]);
+        assert_eq!(files(Some(2)), vec!["b.md", "d.md"]);
+    }
+}
@@ -1,14 +1,10 @@
 use std::io::{Read, Write};
-use std::net::{IpAddr, SocketAddr};
 use std::path::{Path, PathBuf};
 use std::fs::{create_dir_all, File};
-use anyhow::{Context, Error, Result};
+use anyhow::{Context, Result};
 
 // Re-exports from libs
-use libs::{
-    walkdir::WalkDir,
-    ws::Sender,
-};
+use libs::walkdir::WalkDir;
 
 // Local modules
 use crate::site::Site;
@@ -84,6 +80,8 @@ pub fn copy_file(src: &Path, dest: &Path, base_path: &Path)-> Result<()> {
     let target_path = dest.join(relative_path);
 
     create_parent(&target_path)?;
+    std::fs::copy(src, &target_path)
+        .with_context(|| format!("Failed to copy {} to {}", src.display(), target_path.display()))?;
 
     Ok(())
 }
@@ -104,7 +102,7 @@ pub fn copy_directory(src: &Path, dest: &Path) -> Result<()>{
           
         }
         else {
-            copy_file(entry.path(), &target_path, src)?;
+            copy_file(entry.path(), dest, src)?;
         }
     }
 
@@ -113,48 +111,6 @@ pub fn copy_directory(src: &Path, dest: &Path) -> Result<()>{
 
 
 
-pub fn generate_site(
-    root_dir: &Path,
-    interface: IpAddr,
-    interface_port: u16,
-    output_dir: Option<&Path>,
-    force: bool,
-    base_url: Option<&str>,
-    config_file: &Path,
-    mut no_port_append: bool,
-) -> Result<(Site, SocketAddr, String)> {
-
-    let mut site = Site::new(root_dir, config_file)?;
-    let address = SocketAddr::new(interface, interface_port);
-
-    //when no base url is provided, use the interface address
-    let base_url = base_url.map_or_else(
-        || {
-            no_port_append = true; 
-            address.to_string()
-        },
-        |xm | xm.to_string(),
-    );
-
-
-    if let Some(output_dir) = output_dir {
-        if !force && output_dir.exists() {
-            return Err(Error::msg(format!(
-                "Directory '{}' already exists. Use --force to overwrite.",
-                output_dir.display(),
-            )));
-        }
-        site.set_output_path(output_dir);
-    }
-
-    site.load_files()?; 
-
-    site.build_output_dir()?;
-
-    Ok((site, address, base_url))
-}
-
-
 /// Builds the output directory for the site.
 ///
 /// If `output_dir` is given and exists, it will be removed unless `force` is `false`.
@@ -211,33 +167,3 @@ pub fn build_output_dir(root_dir: &Path, config_file: &Path, output_dir: Option<
 //         }
 //     }
 // }
-
-/// Builds the output directory for the site and sends a reload message to the broadcaster
-///
-/// # Arguments
-///
-/// * `broadcaster` - A reference to the broadcaster
-/// * `res` - The result of the build operation
-/// * `reload_path` - The path to reload
-pub fn build_output_dir_with_broadcaster(broadcaster: &Sender, res: Result<()>, reload_path: &str) {
-    match res {
-        Ok(_)  => {
-            broadcaster.send(format!(
-                r#"
-                {{
-                    "command": "reload",
-                    "path": {},
-                    "originalPath": "",
-                    "liveCSS": true,
-                    "liveImg": true,
-                    "protocol": ["http://livereload.com/protocols/official-7"]
-                }}"#,
-                format!(r#""{}""#, reload_path)
-            ))
-            .unwrap();
-        }
-        Err(e) => {
-            println!("Error while building the site: {}", e);
-        }
-    }
-}
\ No newline at end of file
@@ -4,4 +4,14 @@ pub mod fs;
 pub mod net;
 pub mod site;
 pub mod page;
+pub mod section;
+pub mod taxonomies;
+pub mod pagination;
 pub mod content;
+pub mod templates;
+pub mod link_checking;
+pub mod sitemap;
+pub mod feeds;
+pub mod search;
+pub mod sass;
+pub mod theme;
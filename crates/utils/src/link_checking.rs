@@ -0,0 +1,439 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use libs::reqwest::{self, StatusCode};
+use libs::serde_json;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{Mutex, Semaphore};
+
+use crate::content::InternalLink;
+use crate::page::Page;
+use crate::section::Section;
+
+/// `id` attributes of the rendered HTML, headings included
+static ID_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r#"\sid="([^"]*)""#).unwrap());
+
+/// A link pointing to a page that doesn't exist or to an anchor missing from its target
+#[derive(Debug, PartialEq)]
+pub struct BrokenLink {
+    /// File the link was found in
+    pub file: String,
+    /// Line of the link in that file, starting at 1
+    pub line: usize,
+    pub message: String,
+}
+
+/// Returns every anchor that can be linked to in some rendered HTML
+fn anchors(html: &str) -> Vec<&str> {
+    ID_REGEX
+        .captures_iter(html)
+        .filter_map(|captures| captures.get(1))
+        .map(|id| id.as_str())
+        .collect()
+}
+
+/// Finds the internal links of the site whose target page or anchor doesn't exist
+///
+/// Must be called once the markdown of the site has been rendered, `@/` links to missing
+/// files being kept unresolved until then so that they're all reported at once.
+pub fn find_broken_internal_links(
+    pages: &HashMap<PathBuf, Page>,
+    sections: &HashMap<PathBuf, Section>,
+) -> Vec<BrokenLink> {
+    // rendered HTML of every page and section, by path relative to the content directory
+    let mut contents: HashMap<&str, &str> = HashMap::new();
+    let mut sources: Vec<(&str, usize, &[InternalLink])> = Vec::new();
+
+    for page in pages.values() {
+        contents.insert(&page.relative_path, &page.content);
+        sources.push((&page.file, page.body_line_offset, &page.internal_links));
+    }
+    for section in sections.values() {
+        contents.insert(&section.relative_path, &section.content);
+        sources.push((&section.file, section.body_line_offset, &section.internal_links));
+    }
+
+    let mut broken = Vec::new();
+    for (file, body_line_offset, links) in sources {
+        for link in links {
+            let message = match (contents.get(link.relative_path.as_str()), &link.anchor) {
+                // `@/` links whose target isn't a page or section of the site
+                (None, _) => format!("`@/{}` doesn't exist", link.relative_path),
+                (Some(content), Some(anchor)) if !anchors(content).contains(&anchor.as_str()) => {
+                    format!("anchor `#{}` doesn't exist in `@/{}`", anchor, link.relative_path)
+                }
+                _ => continue,
+            };
+
+            broken.push(BrokenLink {
+                file: file.to_string(),
+                line: body_line_offset + link.line,
+                message,
+            });
+        }
+    }
+
+    broken.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    broken
+}
+
+/// Fails with every broken internal link of the site, see [`find_broken_internal_links`]
+pub fn check_internal_links(
+    pages: &HashMap<PathBuf, Page>,
+    sections: &HashMap<PathBuf, Section>,
+) -> Result<()> {
+    let broken = find_broken_internal_links(pages, sections);
+    if broken.is_empty() {
+        return Ok(());
+    }
+
+    let mut message = format!("Found {} broken internal link(s):", broken.len());
+    for link in &broken {
+        message.push_str(&format!("\n  {}:{}: {}", link.file, link.line, link.message));
+    }
+    bail!(message)
+}
+
+fn default_cache_file() -> String {
+    String::from(".link-checker-cache.json")
+}
+
+/// The `[link_checker]` section of the config
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LinkCheckerConfig {
+    /// Links starting with one of these are never checked, e.g. sites blocking bots
+    pub skip_prefixes: Vec<String>,
+    /// Seconds to wait for an answer before giving up on a link
+    pub timeout: u64,
+    /// How many links are checked at the same time
+    pub concurrency: usize,
+    /// Milliseconds to wait between two requests to the same domain
+    pub domain_interval: u64,
+    /// Seconds a working link isn't checked again for, 0 disables the cache
+    pub cache_ttl: u64,
+    /// File remembering which links worked recently, relative to the root of the site
+    pub cache_file: String,
+}
+
+impl Default for LinkCheckerConfig {
+    fn default() -> LinkCheckerConfig {
+        LinkCheckerConfig {
+            skip_prefixes: Vec::new(),
+            timeout: 10,
+            concurrency: 8,
+            domain_interval: 500,
+            cache_ttl: 24 * 60 * 60,
+            cache_file: default_cache_file(),
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// Links found working, with when they were checked in seconds since the epoch
+fn read_cache(path: &Path, ttl: u64) -> HashMap<String, u64> {
+    let cache: HashMap<String, u64> = std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default();
+
+    let now = now();
+    cache.into_iter().filter(|(_, checked)| checked + ttl > now).collect()
+}
+
+/// Checks a single link, trying a GET when the server doesn't like HEAD requests
+async fn check_url(client: &reqwest::Client, url: &str) -> std::result::Result<(), String> {
+    let status = match client.head(url).send().await {
+        Ok(response) if response.status().is_success() => return Ok(()),
+        Ok(response) => Some(response.status()),
+        Err(_) => None,
+    };
+
+    match client.get(url).send().await {
+        Ok(response) if response.status().is_success() => Ok(()),
+        Ok(response) => Err(response.status().to_string()),
+        Err(err) if err.is_timeout() => Err("timed out".to_string()),
+        Err(err) => Err(status.map_or_else(|| err.to_string(), |status: StatusCode| status.to_string())),
+    }
+}
+
+/// Checks every link once, returning the error of those that can't be reached
+///
+/// Links are checked `concurrency` at a time, and requests to the same domain are spaced
+/// by `domain_interval` not to get rate limited. Working links are remembered in the
+/// cache at `cache_path` and not checked again until `cache_ttl` has elapsed.
+pub async fn check_urls(
+    urls: HashSet<String>,
+    config: &LinkCheckerConfig,
+    cache_path: Option<&Path>,
+) -> HashMap<String, String> {
+    let mut cache = match cache_path {
+        Some(path) if config.cache_ttl > 0 => read_cache(path, config.cache_ttl),
+        _ => HashMap::new(),
+    };
+
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(config.timeout))
+        .user_agent(concat!("genetics/", env!("CARGO_PKG_VERSION"), " link checker"))
+        .build()
+        .expect("Unable to build the HTTP client");
+    let semaphore = Arc::new(Semaphore::new(config.concurrency.max(1)));
+    let mut last_requests: HashMap<String, Arc<Mutex<Option<Instant>>>> = HashMap::new();
+    let interval = Duration::from_millis(config.domain_interval);
+
+    let mut tasks = Vec::new();
+    for url in urls {
+        if cache.contains_key(&url) || config.skip_prefixes.iter().any(|prefix| url.starts_with(prefix)) {
+            continue;
+        }
+
+        let domain = reqwest::Url::parse(&url)
+            .ok()
+            .and_then(|parsed| parsed.host_str().map(str::to_string))
+            .unwrap_or_default();
+        let last_request = last_requests.entry(domain).or_default().clone();
+        let semaphore = semaphore.clone();
+        let client = client.clone();
+
+        tasks.push(tokio::spawn(async move {
+            // waiting for its domain doesn't hold a permit other domains could use
+            let _permit = {
+                let mut last_request = last_request.lock().await;
+                if let Some(last) = *last_request {
+                    tokio::time::sleep_until((last + interval).into()).await;
+                }
+                let permit = semaphore.acquire_owned().await.unwrap();
+                *last_request = Some(Instant::now());
+                permit
+            };
+
+            let result = check_url(&client, &url).await;
+            (url, result)
+        }));
+    }
+
+    let mut errors = HashMap::new();
+    for task in tasks {
+        match task.await {
+            Ok((url, Ok(()))) => {
+                cache.insert(url, now());
+            }
+            Ok((url, Err(err))) => {
+                errors.insert(url, err);
+            }
+            Err(err) => println!("A link check panicked: {}", err),
+        }
+    }
+
+    if let Some(path) = cache_path.filter(|_| config.cache_ttl > 0) {
+        if let Ok(content) = serde_json::to_string_pretty(&cache) {
+            // failing to write the cache only makes the next check slower
+            let _ = std::fs::write(path, content);
+        }
+    }
+
+    errors
+}
+
+/// Fails with every external link of the site that can't be reached, see [`check_urls`]
+pub fn check_external_links(
+    pages: &HashMap<PathBuf, Page>,
+    sections: &HashMap<PathBuf, Section>,
+    config: &LinkCheckerConfig,
+    cache_path: Option<&Path>,
+) -> Result<()> {
+    let sources: Vec<(&str, &[String])> = pages
+        .values()
+        .map(|page| (page.file.as_str(), page.external_links.as_slice()))
+        .chain(sections.values().map(|section| (section.file.as_str(), section.external_links.as_slice())))
+        .collect();
+
+    let urls = sources.iter().flat_map(|(_, links)| links.iter().cloned()).collect();
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .context("Unable to start the link checker")?;
+    let errors = runtime.block_on(check_urls(urls, config, cache_path));
+    let errors = &errors;
+
+    let mut broken: Vec<String> = sources
+        .iter()
+        .flat_map(|(file, links)| {
+            links.iter().filter_map(move |link| {
+                errors.get(link).map(|err| format!("\n  {}: {} ({})", file, link, err))
+            })
+        })
+        .collect();
+    if broken.is_empty() {
+        return Ok(());
+    }
+
+    broken.sort();
+    broken.dedup();
+    bail!("Found {} broken external link(s):{}", broken.len(), broken.concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(relative_path: &str, content: &str, internal_links: Vec<InternalLink>) -> Page {
+        Page {
+            file: format!("content/{}", relative_path),
+            relative_path: relative_path.to_string(),
+            body_line_offset: 3,
+            content: content.to_string(),
+            internal_links,
+            ..Page::default()
+        }
+    }
+
+    fn link(relative_path: &str, anchor: Option<&str>, line: usize) -> InternalLink {
+        InternalLink {
+            relative_path: relative_path.to_string(),
+            anchor: anchor.map(str::to_string),
+            line,
+        }
+    }
+
+    #[test]
+    fn test_reports_every_broken_anchor_with_its_line() {
+        let sections = HashMap::new();
+        let pages = [
+            page("docs/guide.md", r#"<h2 id="install">Install</h2>"#, vec![]),
+            page(
+                "index.md",
+                "",
+                vec![
+                    link("docs/guide.md", Some("install"), 1),
+                    link("docs/guide.md", Some("setup"), 4),
+                    link("docs/guide.md", None, 5),
+                    link("docs/missing.md", None, 7),
+                ],
+            ),
+        ];
+        let pages = pages
+            .into_iter()
+            .map(|page| (PathBuf::from(&page.file), page))
+            .collect();
+
+        let broken = find_broken_internal_links(&pages, &sections);
+        assert_eq!(
+            broken,
+            vec![
+                BrokenLink {
+                    file: "content/index.md".to_string(),
+                    line: 7,
+                    message: "anchor `#setup` doesn't exist in `@/docs/guide.md`".to_string(),
+                },
+                BrokenLink {
+                    file: "content/index.md".to_string(),
+                    line: 10,
+                    message: "`@/docs/missing.md` doesn't exist".to_string(),
+                },
+            ]
+        );
+
+        let err = check_internal_links(&pages, &sections).unwrap_err().to_string();
+        assert!(err.starts_with("Found 2 broken internal link(s):"));
+        assert!(err.contains("content/index.md:7: anchor `#setup`"));
+    }
+
+    /// Starts a server answering `/ok`, `/get-only` (405 to HEAD), `/slow` and 404 otherwise,
+    /// returning its address and the number of requests it got
+    async fn stub_server() -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Body, Method, Response, Server};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let make_service = make_service_fn(move |_| {
+            let counter = counter.clone();
+            async move {
+                Ok::<_, hyper::Error>(service_fn(move |req: hyper::Request<Body>| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        let status = match (req.method(), req.uri().path()) {
+                            (_, "/ok") => 200,
+                            (&Method::HEAD, "/get-only") => 405,
+                            (_, "/get-only") => 200,
+                            (_, "/slow") => {
+                                tokio::time::sleep(Duration::from_secs(3)).await;
+                                200
+                            }
+                            _ => 404,
+                        };
+                        let response = Response::builder().status(status).body(Body::empty()).unwrap();
+                        Ok::<_, hyper::Error>(response)
+                    }
+                }))
+            }
+        });
+
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let address = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        (address, requests)
+    }
+
+    fn test_config() -> LinkCheckerConfig {
+        LinkCheckerConfig { timeout: 1, domain_interval: 0, ..LinkCheckerConfig::default() }
+    }
+
+    #[tokio::test]
+    async fn test_check_urls_against_stub_server() {
+        let (address, _) = stub_server().await;
+        let urls = ["/ok", "/get-only", "/missing", "/slow", "/skipped"]
+            .iter()
+            .map(|path| format!("{}{}", address, path))
+            .collect();
+        let config = LinkCheckerConfig {
+            skip_prefixes: vec![format!("{}/skipped", address)],
+            ..test_config()
+        };
+
+        let errors = check_urls(urls, &config, None).await;
+
+        let mut broken: Vec<_> = errors.iter().map(|(url, err)| (url.replace(&address, ""), err.as_str())).collect();
+        broken.sort();
+        assert_eq!(broken, vec![("/missing".to_string(), "404 Not Found"), ("/slow".to_string(), "timed out")]);
+    }
+
+    #[tokio::test]
+    async fn test_check_urls_caches_working_links() {
+        use std::sync::atomic::Ordering;
+
+        let (address, requests) = stub_server().await;
+        let dir = tempfile::tempdir().unwrap();
+        let cache_path = dir.path().join(default_cache_file());
+        let urls: HashSet<String> = [format!("{}/ok", address), format!("{}/missing", address)].into();
+
+        check_urls(urls.clone(), &test_config(), Some(&cache_path)).await;
+        let first_run = requests.load(Ordering::SeqCst);
+        let errors = check_urls(urls, &test_config(), Some(&cache_path)).await;
+
+        // only the broken link is checked again, with a HEAD then a GET
+        assert_eq!(requests.load(Ordering::SeqCst) - first_run, 2);
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_check_urls_spaces_requests_to_a_domain() {
+        let (address, _) = stub_server().await;
+        let urls = (0..3).map(|i| format!("{}/ok?{}", address, i)).collect();
+        let config = LinkCheckerConfig { domain_interval: 200, ..test_config() };
+
+        let start = Instant::now();
+        check_urls(urls, &config, None).await;
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+}
@@ -1,286 +1,236 @@
 use std::{
     net::{IpAddr, TcpListener},
     path::{Path, PathBuf},
-    sync::mpsc::channel,
-    thread,
-    time::Duration,
+    sync::{Arc, RwLock},
 };
-use errors::{ anyhow, Context, Result};
-use hyper::{
-    service::{make_service_fn, service_fn},
-    Body, Response, Server, StatusCode,
-};
-use notify_debouncer_full::{new_debouncer, notify::RecursiveMode};
-use ws::{Message, Sender, WebSocket};
-use crate::fs::{ build_output_dir_with_broadcaster, generate_site, create_directory };
+use hyper::{header, Body, Method, Request, Response, StatusCode};
+use libs::mime_guess;
+use libs::percent_encoding::percent_decode_str;
+use crate::site::MemoryOutput;
 
 
 
-#[derive(Debug, PartialEq)]
-pub enum WatchStatus {
-    Required, 
-    Optional, 
-    Conditional(bool),
+/// Gets an available port
+pub fn get_available_port(interface:IpAddr, prevent: u16) -> Option<u16> {
+    (1024..9000).find(|port| *port != prevent && available_port_checker(interface, *port))
 }
 
-// #[derive(Debug, Clone, PartialEq)]
-// pub enum RecursiveMode {
-//     // watch sub-directories
-//     Recursive, 
-
-//     // watch provided directory
-//     NonRecursive,
-// }
-
-
-// impl RecursiveMode {
-//     pub(crate) fn is_recursive(&self) -> bool {
-//         match *self {
-//             RecursiveMode::Recursive => true, 
-//             RecursiveMode::NonRecursive => false,  
-//         }
-//     }
-// }
-
-
-pub fn serve_site(
-    root_dir: &Path,
-    interface: IpAddr,
-    interface_port: u16,
-    output_dir: Option<&Path>,
-    force: bool,
-    base_url: Option<&str>,
-    config_file: &Path,
-    open: bool,
-    no_port_append: bool,
-) ->  Result<()> {
-   // let start = Instant::now();
-
-    let (site, address, constructed_base_url) = generate_site(
-        root_dir,
-        interface,
-        interface_port,
-        output_dir,
-        force,
-        base_url,
-        config_file,
-        no_port_append,
-    )?;
-
-    // let base_path = match constructed_base_url.splitn(4, "/").nth(3) {
-    //     Some(xm) => format!("/{}", xm), 
-    //     None => "/".to_string(),
-    // };
-
-    
-    if(TcpListener::bind(address)).is_err() {
-        return Err(anyhow!("Cannot start server on address {}.", address));
-    }
+/// Checks if a port is available
+pub fn available_port_checker(interface:IpAddr, port: u16) -> bool {
+    TcpListener::bind((interface, port)).is_ok()
+}
 
-    // let config_buf = PathBuf::from(config_file); 
-    let root_dir_str = root_dir.to_str().expect("Invalid root directory");
-
-    let watch_vector = vec![
-        (root_dir_str, WatchStatus::Required, RecursiveMode::NonRecursive),
-        ("content", WatchStatus::Required, RecursiveMode::NonRecursive),
-        ("static", WatchStatus::Optional, RecursiveMode::Recursive),
-        ("templates", WatchStatus::Optional, RecursiveMode::Recursive),
-       // ("themes", WatchStatus::Conditional(site.config.themes.is_some()), RecursiveMode::Recursive),
-    ];
-
-    //let (tx, rx) = channel();
-    let (tx, _) = channel();
-    let mut debouncer = new_debouncer(Duration::from_secs(1), None, tx).unwrap();
-
-
-    let mut watchers = Vec::new();
-    for (entry, mode, recursive_mode) in watch_vector {
-        let watch_path = root_dir.join(entry);
-        let watch_state = match mode {
-            WatchStatus::Required => true,
-            WatchStatus::Optional => watch_path.exists(),
-            WatchStatus::Conditional(x) => x && watch_path.exists(),
-        };
 
-        if watch_state {
-            debouncer.watch(
-                &root_dir.join(entry),
-                recursive_mode,
-            )
-            .with_context(|| format!("Unable to watch directory {}", entry))?;
-        }
-        watchers.push(entry.to_string());
-    }
 
+/// Default port of the livereload WebSocket server, the one livereload clients expect
+pub const DEFAULT_LIVE_RELOAD_PORT: u16 = 35729;
 
-    //watch the directories 
-    // websocket 
-    const DEFAULT_WS_PORT: u16 = 8080;
-    let ws_port = site.config.live_reload.unwrap_or(DEFAULT_WS_PORT);
-    let ws_address = format!("{}:{}", interface, ws_port);
-    let output_path = site.output_path.clone();
-
-  //  let static_root_path = std::fs::canonicalize(&output_path).unwrap(); //the output directory can be changed
-
-    let broadcaster = {
-        thread::spawn(move || {
-            let rt = tokio::runtime::Builder::new_current_thread()
-                .enable_all()
-                .build()
-                .expect("Could not tokio builder");
-
-            rt.block_on(async {
-                let servelet = make_service_fn(move |_| {
-                   // let static_root = static_root_path.clone();
-                   // let base_path = base_path.clone(); 
-
-                    async {
-                        Ok::<_, hyper::Error>(service_fn(move |_| async {
-                            let response = Response::builder()
-                                .status(StatusCode::OK)
-                                .body(Body::empty())
-                                .unwrap();
-                            Ok::<_, hyper::Error>(response)
-                        }))
-                    }
-                });
-
-
-                let server = Server::bind(&address).serve(servelet); 
-
-                println!("Listening on {}, {}", constructed_base_url, address);
-
-                if open {
-                    if let Err(err) = open::that(&constructed_base_url) {
-                        println!("Unable to open URL: {}", err);
-                    }
-                }
-
-
-                server.await.expect("Unable to start server");
-            })
-        }); 
-
-
-        let ws_server = WebSocket::new(|output: Sender| {
-            move |msg: Message| {
-                if msg.into_text().unwrap().contains("hello") {
-                    return output.send(Message::text(
-                        r#"
-                        {
-                            "command": "hello",
-                            "protocols": [ "http://livereload.com/protocols/official-7" ],
-                            "serverName": "Genetics"
-                        }
-                    "#
-                    ))
-                }
-
-                Ok(())
-            }
-        })
-        .unwrap();
+const LIVE_RELOAD_SCRIPT: &str = include_str!("builtins/livereload.js");
+
+/// What the development server needs to answer requests
+#[derive(Debug, Clone)]
+pub struct DevServer {
+    /// Directory the site is built into
+    pub output_path: PathBuf,
+    /// Files of the site when it is built in memory rather than in `output_path`
+    pub memory_output: Option<MemoryOutput>,
+    /// Port of the livereload WebSocket server the injected script connects to
+    pub live_reload_port: u16,
+    /// Where the site is mounted, `/` or the path of the base URL like `/docs/`, shared
+    /// with the watcher so that a change of `base_url` moves the site
+    pub base_path: Arc<RwLock<String>>,
+}
 
-        let broadcaster = ws_server.broadcaster();
+/// Adds the livereload client to a served HTML page, right before `</body>` or at the
+/// end of documents without one
+///
+/// Only the development server does this, built sites never contain the script.
+pub fn inject_live_reload(html: &[u8], live_reload_port: u16) -> Vec<u8> {
+    let script = format!(
+        "<script>{}</script>",
+        LIVE_RELOAD_SCRIPT.replace("__LIVERELOAD_PORT__", &live_reload_port.to_string())
+    );
+
+    let lowercase = html.to_ascii_lowercase();
+    let position = lowercase
+        .windows(b"</body>".len())
+        .rposition(|window| window == b"</body>")
+        .unwrap_or(html.len());
+
+    let mut injected = Vec::with_capacity(html.len() + script.len());
+    injected.extend_from_slice(&html[..position]);
+    injected.extend_from_slice(script.as_bytes());
+    injected.extend_from_slice(&html[position..]);
+    injected
+}
 
-        let ws_server = match ws_server.bind(&*ws_address) {
-            Ok(server) => server,
-            Err(err) => {
-                eprintln!("Unable to bind to address: {}", err);
-                return Err(anyhow!("Failed to bind WebSocket server: {}", err));
+/// Normalizes the path of a request to `/a/b`, without a trailing slash
+///
+/// Returns `None` for paths trying to escape the output, like `/../secret`.
+fn normalize_request_path(request_path: &str) -> Option<String> {
+    let decoded = percent_decode_str(request_path).decode_utf8().ok()?;
+
+    let mut path = String::new();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => return None,
+            // `\` would be a separator on Windows and `:` could name a drive
+            segment if segment.contains(['\\', ':']) => return None,
+            segment => {
+                path.push('/');
+                path.push_str(segment);
             }
-        };
+        }
+    }
 
+    Some(path)
+}
 
-        thread::spawn(move || {
-            ws_server.run().unwrap();
-        });
+/// What a normalized request path points to in the output of the site
+enum Lookup {
+    /// The path of the file, for its content type, and its content
+    File(String, Vec<u8>),
+    /// A directory requested without the trailing slash
+    Directory,
+    Missing,
+}
 
-        broadcaster
-    };
+async fn lookup_on_disk(root: &Path, path: &str, is_directory_request: bool) -> Lookup {
+    let mut file_path = root.join(path.trim_start_matches('/'));
+    if file_path.is_dir() {
+        if !is_directory_request {
+            return Lookup::Directory;
+        }
+        file_path.push("index.html");
+    }
 
-    //we can watch for changes in the config file
-    let config_path = PathBuf::from(config_file);
-    let config_name = config_path.file_name().unwrap().to_str().expect("Invalid config file");
-    let watch_list = watchers
-        .iter()
-        .map(|entry| if *entry == root_dir_str { config_name } else { entry })
-        .collect::<Vec<&str>>()
-        .join(",");
-
-    println!("\nWatching directories: {}", watch_list);
-    create_directory(&output_path)?;
-
-    // let watch_list = watchers
-    //     .iter()
-    //     .map(|w| if w == root_dir_str { config_buf } else { w })
-    //     .collect::<Vec<_>>()
-    //     .join(",");
-
-    // println!("\nWatching directories: {}", watch_list);
-
-    println!("Use Ctrl+C to stop\n");
-
-    ctrlc::set_handler(move || {
-        println!("\nShutting down...");
-        ::std::process::exit(0);
-    })
-    .expect("Unable to set Ctrl+C handler");
-
-    // let templates = |site: &mut Site| {
-    //     build_output_dir_with_broadcaster(
-    //         &broadcaster, 
-    //         site.reload_templates, 
-    //         &site.templates_path.to_string_lossy()
-    //     );
-    // };
-
-    
-    let create_site = move || -> Result<()> {
-        let _ = match generate_site(
-            root_dir, 
-            interface, 
-            interface_port, 
-            output_dir, 
-            force, 
-            base_url, 
-            config_file, 
-            no_port_append
-    ) {
-            Ok((_, _, _)) => {
-                //clean up serve error if there's
-                // perform rebuilding of site
-                build_output_dir_with_broadcaster(
-                    &broadcaster, 
-                    Ok(()), 
-                    "/x.js"
-                );
-                Ok(())
-            },
-            Err(err) => {
-                println!("Unable to serve site: {}", err);
-                Err(err)
-            },
-        };
-        Ok(())
+    // symlinks in the output could still point outside of it
+    let inside_root = match (file_path.canonicalize(), root.canonicalize()) {
+        (Ok(file_path), Ok(root)) => file_path.starts_with(root),
+        _ => false,
     };
+    if !inside_root {
+        return Lookup::Missing;
+    }
 
-    create_site() //run the site
+    match tokio::fs::read(&file_path).await {
+        Ok(content) => Lookup::File(file_path.to_string_lossy().to_string(), content),
+        Err(_) => Lookup::Missing,
+    }
 }
 
+fn lookup_in_memory(memory_output: &MemoryOutput, path: &str, is_directory_request: bool) -> Lookup {
+    let files = memory_output.read().unwrap();
+    let index = format!("{}/index.html", path);
 
+    if !path.is_empty() && !is_directory_request {
+        if let Some(content) = files.get(path) {
+            return Lookup::File(path.to_string(), content.clone());
+        }
+    }
 
-/// Gets an available port
-pub fn get_available_port(interface:IpAddr, prevent: u16) -> Option<u16> {
-    (1024..9000).find(|port| *port != prevent && available_port_checker(interface, *port))
+    match files.get(&index) {
+        Some(_) if !is_directory_request => Lookup::Directory,
+        Some(content) => Lookup::File(index, content.clone()),
+        None => Lookup::Missing,
+    }
 }
 
-/// Checks if a port is available
-pub fn available_port_checker(interface:IpAddr, port: u16) -> bool {
-    TcpListener::bind((interface, port)).is_ok()
+async fn lookup(server: &DevServer, path: &str, is_directory_request: bool) -> Lookup {
+    match &server.memory_output {
+        Some(memory_output) => lookup_in_memory(memory_output, path, is_directory_request),
+        None => lookup_on_disk(&server.output_path, path, is_directory_request).await,
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(Body::from(status.canonical_reason().unwrap_or_default()))
+        .unwrap()
+}
+
+/// The `404.html` of the site if it has one, a plain text response otherwise
+async fn not_found(server: &DevServer) -> Response<Body> {
+    match lookup(server, "/404.html", false).await {
+        Lookup::File(_, content) => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(Body::from(inject_live_reload(&content, server.live_reload_port)))
+            .unwrap(),
+        _ => status_response(StatusCode::NOT_FOUND),
+    }
 }
 
+/// Answers a request of the development server with the files of the site, from memory
+/// or from its output path
+///
+/// Directories are served through their `index.html`, and requested without a trailing
+/// slash they are redirected to the URL with one so relative links keep working.
+/// HTML responses get the livereload client injected.
+pub async fn handle_request(req: Request<Body>, server: DevServer) -> Result<Response<Body>, hyper::Error> {
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return Ok(status_response(StatusCode::METHOD_NOT_ALLOWED));
+    }
+
+    let request_path = req.uri().path();
+
+    // the site lives under the path of its base URL, nothing else exists
+    let base_path = server.base_path.read().unwrap().clone();
+    let mount_path = base_path.trim_end_matches('/');
+    if (request_path == "/" || request_path == mount_path) && request_path != base_path {
+        return Ok(Response::builder()
+            .status(StatusCode::FOUND)
+            .header(header::LOCATION, base_path.as_str())
+            .body(Body::empty())
+            .unwrap());
+    }
+    let site_path = match request_path.strip_prefix(mount_path) {
+        Some(site_path) if site_path.starts_with('/') => site_path,
+        _ => return Ok(not_found(&server).await),
+    };
+
+    let path = match normalize_request_path(site_path) {
+        Some(path) => path,
+        None => return Ok(status_response(StatusCode::FORBIDDEN)),
+    };
+
+    let (file_path, content) = match lookup(&server, &path, request_path.ends_with('/')).await {
+        Lookup::File(file_path, content) => (file_path, content),
+        Lookup::Directory => {
+            let location = match req.uri().query() {
+                Some(query) => format!("{}/?{}", request_path, query),
+                None => format!("{}/", request_path),
+            };
+            return Ok(Response::builder()
+                .status(StatusCode::MOVED_PERMANENTLY)
+                .header(header::LOCATION, location)
+                .body(Body::empty())
+                .unwrap());
+        }
+        Lookup::Missing => return Ok(not_found(&server).await),
+    };
+
+    let mime = mime_guess::from_path(&file_path).first_or_octet_stream();
+    let content_type = match mime.type_() {
+        mime_guess::mime::TEXT => format!("{}; charset=utf-8", mime.essence_str()),
+        _ => mime.essence_str().to_string(),
+    };
 
+    let content = if mime.essence_str() == "text/html" {
+        inject_live_reload(&content, server.live_reload_port)
+    } else {
+        content
+    };
+
+    let body = if req.method() == Method::HEAD { Body::empty() } else { Body::from(content) };
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .body(body)
+        .unwrap())
+}
 
 /// Checks if a link is external
 pub fn is_external_link(link: &str) -> bool {
@@ -289,5 +239,157 @@ pub fn is_external_link(link: &str) -> bool {
 
 #[cfg(test)]
 mod test {
-    
+    use super::*;
+    use hyper::body::to_bytes;
+
+    async fn get(root: &Path, uri: &str) -> Response<Body> {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let server = DevServer {
+            output_path: root.to_path_buf(),
+            memory_output: None,
+            live_reload_port: DEFAULT_LIVE_RELOAD_PORT,
+            base_path: Arc::new(RwLock::new("/".to_string())),
+        };
+        handle_request(req, server).await.unwrap()
+    }
+
+    async fn body(response: Response<Body>) -> String {
+        String::from_utf8(to_bytes(response.into_body()).await.unwrap().to_vec()).unwrap()
+    }
+
+    fn output_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("blog/post")).unwrap();
+        std::fs::write(dir.path().join("index.txt"), "home").unwrap();
+        std::fs::write(dir.path().join("index.html"), "<body>home</body>").unwrap();
+        std::fs::write(dir.path().join("blog/post/index.html"), "post").unwrap();
+        std::fs::write(dir.path().join("style.css"), "body {}").unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_serves_files_with_their_content_type() {
+        let dir = output_dir();
+
+        let response = get(dir.path(), "/style.css").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/css; charset=utf-8");
+        assert_eq!(body(response).await, "body {}");
+
+        let response = get(dir.path(), "/blog/post/").await;
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/html; charset=utf-8");
+        assert!(body(response).await.starts_with("post<script>"));
+
+        assert_eq!(body(get(dir.path(), "/index.txt").await).await, "home");
+    }
+
+    #[tokio::test]
+    async fn test_injects_live_reload_before_closing_body() {
+        let dir = output_dir();
+        let html = body(get(dir.path(), "/").await).await;
+
+        assert!(html.starts_with("<body>home<script>"));
+        assert!(html.ends_with("</script></body>"));
+        assert!(html.contains(&format!("var port = {};", DEFAULT_LIVE_RELOAD_PORT)));
+    }
+
+    #[tokio::test]
+    async fn test_serves_from_memory() {
+        let memory_output = MemoryOutput::default();
+        {
+            let mut files = memory_output.write().unwrap();
+            files.insert("/index.html".to_string(), b"home".to_vec());
+            files.insert("/blog/post/index.html".to_string(), b"post".to_vec());
+            files.insert("/style.css".to_string(), b"body {}".to_vec());
+        }
+        let server = DevServer {
+            output_path: PathBuf::from("/nonexistent"),
+            memory_output: Some(memory_output),
+            live_reload_port: DEFAULT_LIVE_RELOAD_PORT,
+            base_path: Arc::new(RwLock::new("/".to_string())),
+        };
+        let get = |uri: &str| {
+            let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            handle_request(req, server.clone())
+        };
+
+        let response = get("/style.css").await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/css; charset=utf-8");
+        assert_eq!(body(response).await, "body {}");
+
+        assert!(body(get("/").await.unwrap()).await.starts_with("home<script>"));
+        assert!(body(get("/blog/post/").await.unwrap()).await.starts_with("post<script>"));
+
+        let response = get("/blog/post").await.unwrap();
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers()[header::LOCATION], "/blog/post/");
+
+        assert_eq!(get("/blog/").await.unwrap().status(), StatusCode::NOT_FOUND);
+        assert_eq!(get("/../etc/passwd").await.unwrap().status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_mounts_site_at_base_path() {
+        let dir = output_dir();
+        let server = DevServer {
+            output_path: dir.path().to_path_buf(),
+            memory_output: None,
+            live_reload_port: DEFAULT_LIVE_RELOAD_PORT,
+            base_path: Arc::new(RwLock::new("/docs/".to_string())),
+        };
+        let get = |uri: &str| {
+            let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            handle_request(req, server.clone())
+        };
+
+        for uri in ["/", "/docs"] {
+            let response = get(uri).await.unwrap();
+            assert_eq!(response.status(), StatusCode::FOUND);
+            assert_eq!(response.headers()[header::LOCATION], "/docs/");
+        }
+
+        assert!(body(get("/docs/").await.unwrap()).await.starts_with("<body>home"));
+        assert_eq!(body(get("/docs/style.css").await.unwrap()).await, "body {}");
+        assert_eq!(get("/docs/blog/post").await.unwrap().headers()[header::LOCATION], "/docs/blog/post/");
+        assert_eq!(get("/style.css").await.unwrap().status(), StatusCode::NOT_FOUND);
+        assert_eq!(get("/docsstyle.css").await.unwrap().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_inject_live_reload_uses_last_closing_body() {
+        let html = inject_live_reload(b"<p>&lt;/body&gt;</body></BODY>", 1234);
+        let html = String::from_utf8(html).unwrap();
+        assert!(html.starts_with("<p>&lt;/body&gt;</body><script>"));
+        assert!(html.ends_with("</script></BODY>"));
+    }
+
+    #[tokio::test]
+    async fn test_redirects_directories_to_trailing_slash() {
+        let dir = output_dir();
+        let response = get(dir.path(), "/blog/post?x=1").await;
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.headers()[header::LOCATION], "/blog/post/?x=1");
+    }
+
+    #[tokio::test]
+    async fn test_not_found_uses_site_404() {
+        let dir = output_dir();
+        let response = get(dir.path(), "/missing/").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body(response).await, "Not Found");
+
+        std::fs::write(dir.path().join("404.html"), "custom 404").unwrap();
+        let response = get(dir.path(), "/missing.html").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(body(response).await.starts_with("custom 404<script>"));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_path_traversal() {
+        let dir = output_dir();
+        let root = dir.path().join("blog");
+        assert_eq!(get(&root, "/../index.html").await.status(), StatusCode::FORBIDDEN);
+        assert_eq!(get(&root, "/%2e%2e/index.html").await.status(), StatusCode::FORBIDDEN);
+        assert_eq!(get(&root, "/post/..%2f..%2findex.html").await.status(), StatusCode::FORBIDDEN);
+    }
 }
\ No newline at end of file
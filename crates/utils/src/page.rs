@@ -1,8 +1,14 @@
+use std::collections::HashMap;
 use std::path::Path;
 use regex::Regex; 
-use anyhow::{Ok, Result, Error}; 
+use anyhow::{Result, Error}; 
 use once_cell::sync::Lazy;
+use libs::slug::slugify;
+use libs::serde_yaml;
+use libs::toml::{self, Table, Value};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
 
+use crate::content::{Heading, InternalLink};
 use crate::fs::read_file;
 use crate::site::Config;
 
@@ -13,45 +19,279 @@ static TOML_REGEX: Lazy<Regex> = Lazy::new(|| {
     .unwrap()
 });
 
-#[allow(dead_code)]
+static YAML_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"^[[:space:]]*---(\r?\n(?s).*?(?-s))---[[:space:]]*(?:$|(?:\r?\n((?s).*(?-s))$))",
+    )
+    .unwrap()
+});
+
+/// Filenames like `2024-01-02-hello.md` give both the date and the slug of the page
+static DATE_IN_FILENAME_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(\d{4}-\d{2}-\d{2})[_-](.+)$").unwrap()
+});
+
+/// Accepts `YYYY-MM-DD` dates and RFC 3339 datetimes, the offset being optional,
+/// which is what the `date` filter of Tera parses
+static DATE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\d{4}-\d{2}-\d{2}(?:T\d{2}:\d{2}:\d{2}(?:\.\d+)?(?:Z|[+-]\d{2}:\d{2})?)?$").unwrap()
+});
+
 pub enum FrontMatter<'a> {
+    /// Front matter between `+++` fences
     Toml(&'a str),
+    /// Front matter between `---` fences
+    Yaml(&'a str),
+}
+
+/// The front matter of a page, written either in TOML or in YAML
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PageFrontMatter {
+    pub title: Option<String>,
+
+    pub description: Option<String>,
+
+    /// Date the page was published, either a TOML datetime or a `YYYY-MM-DD` string
+    #[serde(deserialize_with = "deserialize_date")]
+    pub date: Option<String>,
+
+    /// Date the page was last updated
+    #[serde(deserialize_with = "deserialize_date")]
+    pub updated: Option<String>,
+
+    /// Whether the page is a draft
+    pub draft: bool,
+
+    /// Replaces the slug computed from the filename
+    pub slug: Option<String>,
+
+    /// Replaces the whole path of the page, relative to the site root
+    pub path: Option<String>,
+
+    /// Paths that should redirect to this page
+    pub aliases: Vec<String>,
+
+    /// Used to sort pages when their section is sorted by weight
+    pub weight: Option<usize>,
+
+    /// Template used to render the page instead of `page.html`
+    pub template: Option<String>,
+
+    /// Terms of the page, keyed by taxonomy name
+    pub taxonomies: HashMap<String, Vec<String>>,
+
+    /// Any extra data the templates might need
+    pub extra: Table,
+
+    /// Keys of the front matter that aren't fields, e.g. the `layout` of Jekyll, moved
+    /// to `extra` once parsed
+    #[serde(flatten)]
+    unknown: HashMap<String, Option<Value>>,
+}
+
+/// Dates are either TOML datetimes or plain strings, both end up as a string
+fn deserialize_date<'de, D>(deserializer: D) -> std::result::Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Option::<Value>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Value::Datetime(date)) => Ok(Some(date.to_string())),
+        Some(Value::String(date)) => Ok(Some(date)),
+        Some(other) => Err(serde::de::Error::custom(format!(
+            "expected a date, found {}",
+            other.type_str()
+        ))),
+    }
 }
 
-#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// Number of lines in `content` before `body`, which must be a suffix of it
+pub(crate) fn lines_before(content: &str, body: &str) -> usize {
+    content[..content.len() - body.len()].matches('\n').count()
+}
+
+/// The date at the start of a filename like `2024-01-02-hello.md`, if any
+fn date_from_filename(file_path: &Path) -> Option<String> {
+    let stem = file_path.file_stem()?.to_string_lossy();
+    DATE_IN_FILENAME_REGEX.captures(&stem).map(|captures| captures[1].to_string())
+}
+
+/// Names of the directories leading to a file, relative to the content directory
+pub(crate) fn parent_components(relative_path: &Path) -> Vec<String> {
+    relative_path
+        .parent()
+        .map(|parent| {
+            parent
+                .components()
+                .map(|xm| xm.as_os_str().to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Deserializes a TOML or YAML front matter block into `T`
+pub fn parse_front_matter<T>(file_path: &Path, front_matter: &FrontMatter) -> Result<T>
+where
+    T: DeserializeOwned + Default,
+{
+    let meta = match front_matter {
+        FrontMatter::Toml(content) => toml::from_str(content).map_err(|err| {
+            anyhow::anyhow!("Invalid front matter in {}: {}", file_path.display(), err)
+        })?,
+        // an empty YAML document is `null`, not an empty mapping
+        FrontMatter::Yaml(content) if content.trim().is_empty() => T::default(),
+        FrontMatter::Yaml(content) => serde_yaml::from_str(content).map_err(|err| {
+            anyhow::anyhow!("Invalid front matter in {}: {}", file_path.display(), err)
+        })?,
+    };
+
+    Ok(meta)
+}
+
+/// Adds the keys of the front matter that aren't fields to `extra`, where templates can
+/// find them. `extra` wins when both have a key and null values are left out.
+pub(crate) fn move_unknown_keys_to_extra(unknown: &mut HashMap<String, Option<Value>>, extra: &mut Table) {
+    for (key, value) in unknown.drain() {
+        if let Some(value) = value {
+            extra.entry(key).or_insert(value);
+        }
+    }
+}
+
+impl PageFrontMatter {
+    /// Parses the front matter of the page at `file_path`
+    pub fn parse(file_path: &Path, front_matter: &FrontMatter) -> Result<PageFrontMatter> {
+        let mut meta: PageFrontMatter = parse_front_matter(file_path, front_matter)?;
+        move_unknown_keys_to_extra(&mut meta.unknown, &mut meta.extra);
+
+        for (field, date) in [("date", &meta.date), ("updated", &meta.updated)] {
+            if let Some(date) = date {
+                if !DATE_REGEX.is_match(date) {
+                    return Err(anyhow::anyhow!(
+                        "Invalid value `{}` for field `{}` in {}: expected a `YYYY-MM-DD` date or an RFC 3339 datetime",
+                        date,
+                        field,
+                        file_path.display()
+                    ));
+                }
+            }
+        }
+
+        Ok(meta)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct Page {
     pub file: String,
+    pub meta: PageFrontMatter,
+    /// Path of the file relative to the content directory, e.g. `blog/post.md`
+    pub relative_path: String,
+    /// The markdown content, without the front matter
+    pub raw_content: String,
+    /// Number of lines before `raw_content` in the file, i.e. the front matter
+    pub body_line_offset: usize,
+    /// The HTML rendered from `raw_content`
     pub content: String,
-    
+    /// The HTML rendered before the `<!-- more -->` marker, if there is one
+    pub summary: Option<String>,
+    /// Headings of `content`, nested by level
+    pub toc: Vec<Heading>,
+    /// Links to other pages and anchors found in `raw_content`
+    pub internal_links: Vec<InternalLink>,
+    /// Links to other websites found in `raw_content`
+    pub external_links: Vec<String>,
+    pub slug: String,
+    /// Directories between the content directory and the file
+    pub components: Vec<String>,
+    /// URL path of the page, e.g. `/blog/post/`
+    pub path: String,
+    pub permalink: String,
+}
+
+/// Serialized version of a page for template rendering
+#[derive(Debug, Serialize)]
+pub struct SerializedPage<'a> {
+    title: Option<&'a str>,
+    description: Option<&'a str>,
+    date: Option<&'a str>,
+    updated: Option<&'a str>,
+    draft: bool,
+    aliases: &'a [String],
+    weight: Option<usize>,
+    taxonomies: &'a HashMap<String, Vec<String>>,
+    extra: &'a Table,
+    relative_path: &'a str,
+    content: &'a str,
+    summary: Option<&'a str>,
+    toc: &'a [Heading],
+    slug: &'a str,
+    path: &'a str,
+    permalink: &'a str,
 }
 
 impl Page {
     /// Create a new page from a file
-    pub fn new<P: AsRef<Path>>(file_path: P, _base_path: &Path) -> Page{
+    pub fn new<P: AsRef<Path>>(file_path: P, base_path: &Path) -> Page{
         let file_path = file_path.as_ref(); 
+        let relative_path = file_path
+            .strip_prefix(base_path.join("content"))
+            .unwrap_or(file_path);
+
+        let mut components = parent_components(relative_path);
+        let stem = relative_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        // `blog/post/index.md` is the page `blog/post`, next to its assets
+        let stem = match components.last() {
+            Some(_) if stem == "index" => components.pop().unwrap(),
+            _ => stem,
+        };
+
+        let slug = match DATE_IN_FILENAME_REGEX.captures(&stem) {
+            Some(captures) => slugify(&captures[2]),
+            None => slugify(&stem),
+        };
 
         Page {
             file: file_path.display().to_string(),
+            relative_path: relative_path.to_string_lossy().replace('\\', "/"),
+            components,
+            slug,
             ..Self::default()
         }
     }
 
     /// Breakdown files for front matter
+    ///
+    /// Files without any front matter are accepted, they get an empty front matter.
     fn split_content<'a>(file_path: &Path, content: &'a str) -> Result<(FrontMatter<'a>, &'a str), Error> {
-        let regex = if TOML_REGEX.is_match(content) {
-            &TOML_REGEX
+        let (regex, is_toml) = if TOML_REGEX.is_match(content) {
+            (&TOML_REGEX, true)
+        } else if YAML_REGEX.is_match(content) {
+            (&YAML_REGEX, false)
         } else {
-            return Err(anyhow::anyhow!("Invalid front matter format in {}", file_path.display()));
+            return Ok((FrontMatter::Toml(""), content));
         };
 
-        let toml_content = regex.captures(content)
-            .ok_or_else(|| anyhow::anyhow!("Failed to capture TOML content in {}", file_path.display()))?;
+        let captures = regex.captures(content)
+            .ok_or_else(|| anyhow::anyhow!("Failed to capture front matter in {}", file_path.display()))?;
             
-        let front_matter = toml_content.get(1)
+        let front_matter = captures.get(1)
             .ok_or_else(|| anyhow::anyhow!("No front matter found in {}", file_path.display()))?
             .as_str();
 
-        Ok((FrontMatter::Toml(front_matter), content))
+        // the body is optional, a page can be front matter only
+        let body = captures.get(2).map_or("", |xm| xm.as_str());
+
+        if is_toml {
+            Ok((FrontMatter::Toml(front_matter), body))
+        } else {
+            Ok((FrontMatter::Yaml(front_matter), body))
+        }
     }
 
     pub fn split_page_content<'a>(file_path: &Path, content: &'a str) -> Result<(FrontMatter<'a>, &'a str), Error> {
@@ -63,27 +303,227 @@ impl Page {
     fn parse(
         file_path: &Path,
         content: &str,
-        // config: &Config,
+        config: &Config,
         base_path: &Path,
     ) -> Result<Page> {
-        let (_front_matter, content) = Self::split_page_content(file_path, content)?;
+        let (front_matter, body) = Self::split_page_content(file_path, content)?;
         let mut page = Self::new(file_path, base_path); 
+        page.body_line_offset = lines_before(content, body);
 
-        page.content = content.to_string(); 
-        
+        page.meta = PageFrontMatter::parse(file_path, &front_matter)?;
+        if page.meta.date.is_none() {
+            page.meta.date = date_from_filename(file_path);
+        }
+        if let Some(slug) = &page.meta.slug {
+            page.slug = slugify(slug);
+        }
+
+        page.raw_content = body.to_string(); 
+        page.set_path(config);
 
         Ok(page)
     }
 
     /// Read .md files
-    pub fn parse_file<P: AsRef<Path>>(file_path: P, _config: &Config, base_path: &Path) -> Result<Page> {
+    pub fn parse_file<P: AsRef<Path>>(file_path: P, config: &Config, base_path: &Path) -> Result<Page> {
         let path = file_path.as_ref(); 
         let content = read_file(path)?; 
-        let page = Self::parse(path, &content, base_path)?; 
+        let page = Self::parse(path, &content, config, base_path)?; 
         
         Ok(page)
     }
 
-    pub fn render_markdown() {}
+    /// Sets the URL path and permalink of the page
+    ///
+    /// The `path` of the front matter wins, otherwise the path is made of the slugified
+    /// directories leading to the page followed by its slug.
+    fn set_path(&mut self, config: &Config) {
+        let path = match &self.meta.path {
+            Some(path) if path.trim_matches('/').is_empty() => String::from("/"),
+            Some(path) => format!("/{}/", path.trim_matches('/')),
+            None => {
+                let mut path = String::from("/");
+                for component in &self.components {
+                    path.push_str(&slugify(component));
+                    path.push('/');
+                }
+                path.push_str(&self.slug);
+                path.push('/');
+                path
+            }
+        };
+
+        self.permalink = config.make_permalink(&path);
+        self.path = path;
+    }
+
+    /// When the page was last updated, its `updated` date or else its `date`
+    pub fn last_updated(&self) -> Option<&str> {
+        self.meta.updated.as_deref().or(self.meta.date.as_deref())
+    }
+
+    /// Serialize the page for template rendering
+    pub fn serialize(&self) -> SerializedPage<'_> {
+        SerializedPage {
+            title: self.meta.title.as_deref(),
+            description: self.meta.description.as_deref(),
+            date: self.meta.date.as_deref(),
+            updated: self.meta.updated.as_deref(),
+            draft: self.meta.draft,
+            aliases: &self.meta.aliases,
+            weight: self.meta.weight,
+            taxonomies: &self.meta.taxonomies,
+            extra: &self.meta.extra,
+            relative_path: &self.relative_path,
+            content: &self.content,
+            summary: self.summary.as_deref(),
+            toc: &self.toc,
+            slug: &self.slug,
+            path: &self.path,
+            permalink: &self.permalink,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_front_matter() {
+        let content = r#"
+title = "Hello"
+date = 2024-01-02
+updated = "2024-02-03T10:00:00Z"
+draft = true
+aliases = ["/old/"]
+weight = 3
+
+[taxonomies]
+tags = ["rust", "web"]
+
+[extra]
+author = "me"
+"#;
+        let meta = PageFrontMatter::parse(Path::new("post.md"), &FrontMatter::Toml(content)).unwrap();
+
+        assert_eq!(meta.title.as_deref(), Some("Hello"));
+        assert_eq!(meta.date.as_deref(), Some("2024-01-02"));
+        assert_eq!(meta.updated.as_deref(), Some("2024-02-03T10:00:00Z"));
+        assert!(meta.draft);
+        assert_eq!(meta.aliases, vec!["/old/".to_string()]);
+        assert_eq!(meta.weight, Some(3));
+        assert_eq!(meta.taxonomies["tags"], vec!["rust".to_string(), "web".to_string()]);
+        assert_eq!(meta.extra["author"].as_str(), Some("me"));
+    }
+
+    #[test]
+    fn test_front_matter_errors_name_file_and_field() {
+        let err = PageFrontMatter::parse(Path::new("post.md"), &FrontMatter::Toml("draft = \"yes\""))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("post.md"));
+        assert!(err.contains("draft"));
+
+        let err = PageFrontMatter::parse(Path::new("post.md"), &FrontMatter::Toml("date = \"tomorrow\""))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("post.md"));
+        assert!(err.contains("`date`"));
+    }
+
+    #[test]
+    fn test_unknown_keys_go_to_extra() {
+        let yaml = "layout: post\ncategories: [news]\npermalink: ~\nextra:\n  layout: wide";
+        let meta = PageFrontMatter::parse(Path::new("post.md"), &FrontMatter::Yaml(yaml)).unwrap();
+        assert_eq!(meta.extra["layout"].as_str(), Some("wide"));
+        assert_eq!(meta.extra["categories"].as_array().unwrap()[0].as_str(), Some("news"));
+        assert!(!meta.extra.contains_key("permalink"));
+
+        let toml = "title = \"Hello\"\ndate = 2024-01-02\nlinktitle = \"Hi\"";
+        let meta = PageFrontMatter::parse(Path::new("post.md"), &FrontMatter::Toml(toml)).unwrap();
+        assert_eq!(meta.date.as_deref(), Some("2024-01-02"));
+        assert_eq!(meta.extra["linktitle"].as_str(), Some("Hi"));
+    }
+
+    #[test]
+    fn test_dates_must_be_rfc3339() {
+        for date in ["2024-01-02", "2024-01-02T10:00:00", "2024-01-02T10:00:00.5Z", "2024-01-02T10:00:00+02:00"] {
+            let front_matter = format!("date = \"{}\"", date);
+            assert!(PageFrontMatter::parse(Path::new("post.md"), &FrontMatter::Toml(&front_matter)).is_ok(), "{}", date);
+        }
+        for date in ["2024-01-02T10:00", "2024-01-02t10:00:00z", "2024-01-02 10:00:00", "2024-1-2"] {
+            let front_matter = format!("date = \"{}\"", date);
+            assert!(PageFrontMatter::parse(Path::new("post.md"), &FrontMatter::Toml(&front_matter)).is_err(), "{}", date);
+        }
+    }
+
+    #[test]
+    fn test_yaml_and_toml_front_matter_are_equivalent() {
+        let toml = "+++\ntitle = \"Hello\"\ndate = 2024-01-02\n[taxonomies]\ntags = [\"rust\"]\n+++\nBody";
+        let yaml = "---\ntitle: Hello\ndate: 2024-01-02\ntaxonomies:\n  tags: [rust]\n---\nBody";
+
+        let (toml_meta, toml_body) = Page::split_page_content(Path::new("a.md"), toml).unwrap();
+        let (yaml_meta, yaml_body) = Page::split_page_content(Path::new("b.md"), yaml).unwrap();
+        assert!(matches!(yaml_meta, FrontMatter::Yaml(_)));
+        assert_eq!(toml_body, yaml_body);
+        assert_eq!(
+            PageFrontMatter::parse(Path::new("a.md"), &toml_meta).unwrap(),
+            PageFrontMatter::parse(Path::new("b.md"), &yaml_meta).unwrap(),
+        );
+
+        let err = PageFrontMatter::parse(Path::new("b.md"), &FrontMatter::Yaml("draft: yes please"))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("b.md"));
+        assert!(err.contains("draft"));
+    }
+
+    #[test]
+    fn test_missing_front_matter_is_empty() {
+        let (front_matter, body) = Page::split_page_content(Path::new("a.md"), "# Just markdown").unwrap();
+        let meta = PageFrontMatter::parse(Path::new("a.md"), &front_matter).unwrap();
 
-}
\ No newline at end of file
+        assert_eq!(meta, PageFrontMatter::default());
+        assert_eq!(body, "# Just markdown");
+    }
+
+    #[test]
+    fn test_page_body_excludes_front_matter() {
+        let config = Config::parse("base_url = \"https://example.com\"").unwrap();
+        let page = Page::parse(
+            Path::new("/site/content/blog/Hello World.md"),
+            "+++\ntitle = \"Hello\"\n+++\n# Body",
+            &config,
+            Path::new("/site"),
+        )
+        .unwrap();
+
+        assert_eq!(page.raw_content, "# Body");
+        assert_eq!(page.relative_path, "blog/Hello World.md");
+        assert_eq!(page.path, "/blog/hello-world/");
+        assert_eq!(page.permalink, "https://example.com/blog/hello-world/");
+    }
+
+    #[test]
+    fn test_page_slug_rules() {
+        let config = Config::parse("base_url = \"https://example.com/\"").unwrap();
+        let parse = |file: &str, content: &str| {
+            Page::parse(Path::new(file), content, &config, Path::new("/site")).unwrap()
+        };
+
+        let page = parse("/site/content/My Blog/2024-01-02-First Post.md", "");
+        assert_eq!(page.path, "/my-blog/first-post/");
+        assert_eq!(page.meta.date.as_deref(), Some("2024-01-02"));
+
+        let page = parse("/site/content/blog/post/index.md", "");
+        assert_eq!(page.path, "/blog/post/");
+
+        let page = parse("/site/content/blog/post.md", "+++\nslug = \"Other Slug\"\n+++\n");
+        assert_eq!(page.path, "/blog/other-slug/");
+
+        let page = parse("/site/content/blog/post.md", "+++\npath = \"/about/me\"\n+++\n");
+        assert_eq!(page.path, "/about/me/");
+        assert_eq!(page.permalink, "https://example.com/about/me/");
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use serde::Serialize;
+
+use crate::page::{Page, SerializedPage};
+use crate::section::{Section, DEFAULT_PAGINATE_PATH};
+use crate::site::Config;
+use crate::taxonomies::{Taxonomy, TaxonomyTerm};
+
+/// One page of a paginated listing
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pager {
+    /// 1-based index of the pager
+    pub index: usize,
+    /// URL path of the pager, the first one shares the path of what is paginated
+    pub path: String,
+    pub permalink: String,
+    /// File paths of the pages shown on this pager
+    pub pages: Vec<PathBuf>,
+}
+
+/// Splits the pages of a section or taxonomy term into pagers
+#[derive(Clone, Debug, PartialEq)]
+pub struct Paginator {
+    pub paginate_by: usize,
+    /// Path segment put before the pager index, e.g. `page` in `/blog/page/2/`
+    pub paginate_path: String,
+    /// URL path of what is paginated, e.g. `/blog/`
+    pub path: String,
+    pub permalink: String,
+    pub pagers: Vec<Pager>,
+    /// Number of pages across all pagers
+    pub total_pages: usize,
+}
+
+/// Serialized version of the paginator for template rendering, as seen from one pager
+#[derive(Debug, Serialize)]
+pub struct SerializedPaginator<'a> {
+    paginate_by: usize,
+    base_url: &'a str,
+    number_pagers: usize,
+    first: &'a str,
+    last: &'a str,
+    previous: Option<&'a str>,
+    next: Option<&'a str>,
+    pages: Vec<SerializedPage<'a>>,
+    current_index: usize,
+    total_pages: usize,
+}
+
+impl Paginator {
+    /// Creates the pagers of the given pages, there is always at least one pager
+    ///
+    /// An empty `paginate_path` falls back to `page`, pagers would otherwise be at `/blog//2/`.
+    pub fn new(
+        pages: &[PathBuf],
+        paginate_by: usize,
+        paginate_path: &str,
+        path: &str,
+        config: &Config,
+    ) -> Paginator {
+        let paginate_path = match paginate_path.trim_matches('/') {
+            "" => DEFAULT_PAGINATE_PATH,
+            paginate_path => paginate_path,
+        };
+        let chunks: Vec<&[PathBuf]> = if pages.is_empty() {
+            vec![&[]]
+        } else {
+            pages.chunks(paginate_by).collect()
+        };
+
+        let pagers = chunks
+            .into_iter()
+            .enumerate()
+            .map(|(i, chunk)| {
+                let pager_path = if i == 0 {
+                    path.to_string()
+                } else {
+                    format!("{}{}/{}/", path, paginate_path, i + 1)
+                };
+
+                Pager {
+                    index: i + 1,
+                    permalink: config.make_permalink(&pager_path),
+                    path: pager_path,
+                    pages: chunk.to_vec(),
+                }
+            })
+            .collect();
+
+        Paginator {
+            paginate_by,
+            paginate_path: paginate_path.to_string(),
+            path: path.to_string(),
+            permalink: config.make_permalink(path),
+            pagers,
+            total_pages: pages.len(),
+        }
+    }
+
+    /// Paginates a section, if it has a `paginate_by`
+    pub fn from_section(section: &Section, config: &Config) -> Option<Paginator> {
+        match section.meta.paginate_by {
+            Some(paginate_by) if paginate_by > 0 => Some(Self::new(
+                &section.pages,
+                paginate_by,
+                &section.meta.paginate_path,
+                &section.path,
+                config,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Paginates a taxonomy term, if its taxonomy has a `paginate_by`
+    pub fn from_taxonomy_term(taxonomy: &Taxonomy, term: &TaxonomyTerm, config: &Config) -> Option<Paginator> {
+        match taxonomy.kind.paginate_by {
+            Some(paginate_by) if paginate_by > 0 => Some(Self::new(
+                &term.pages,
+                paginate_by,
+                &taxonomy.kind.paginate_path,
+                &term.path,
+                config,
+            )),
+            _ => None,
+        }
+    }
+
+    /// Serialize the paginator for the template rendering of `pager`
+    pub fn serialize<'a>(&'a self, pager: &'a Pager, pages: &'a HashMap<PathBuf, Page>) -> SerializedPaginator<'a> {
+        let index = pager.index - 1;
+
+        SerializedPaginator {
+            paginate_by: self.paginate_by,
+            base_url: &self.permalink,
+            number_pagers: self.pagers.len(),
+            first: &self.pagers[0].permalink,
+            last: &self.pagers[self.pagers.len() - 1].permalink,
+            previous: index.checked_sub(1).map(|i| self.pagers[i].permalink.as_str()),
+            next: self.pagers.get(index + 1).map(|next| next.permalink.as_str()),
+            pages: pager.pages.iter().map(|path| pages[path].serialize()).collect(),
+            current_index: pager.index,
+            total_pages: self.total_pages,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config::parse("base_url = \"https://example.com\"").unwrap()
+    }
+
+    #[test]
+    fn test_paginator_splits_pages() {
+        let pages: Vec<PathBuf> = (0..5).map(|i| PathBuf::from(format!("{}.md", i))).collect();
+        let paginator = Paginator::new(&pages, 2, "page", "/blog/", &config());
+
+        assert_eq!(paginator.pagers.len(), 3);
+        assert_eq!(paginator.pagers[0].path, "/blog/");
+        assert_eq!(paginator.pagers[1].path, "/blog/page/2/");
+        assert_eq!(paginator.pagers[2].permalink, "https://example.com/blog/page/3/");
+        assert_eq!(paginator.pagers[2].pages, vec![PathBuf::from("4.md")]);
+        assert_eq!(paginator.total_pages, 5);
+    }
+
+    #[test]
+    fn test_paginator_links() {
+        let pages: Vec<PathBuf> = (0..3).map(|i| PathBuf::from(format!("{}.md", i))).collect();
+        let all_pages: HashMap<PathBuf, Page> = pages.iter().map(|path| (path.clone(), Page::default())).collect();
+        let paginator = Paginator::new(&pages, 1, "archive", "/", &config());
+
+        let first = paginator.serialize(&paginator.pagers[0], &all_pages);
+        assert_eq!(first.previous, None);
+        assert_eq!(first.next, Some("https://example.com/archive/2/"));
+        assert_eq!(first.current_index, 1);
+
+        let last = paginator.serialize(&paginator.pagers[2], &all_pages);
+        assert_eq!(last.previous, Some("https://example.com/archive/2/"));
+        assert_eq!(last.next, None);
+        assert_eq!(last.last, "https://example.com/archive/3/");
+        assert_eq!(last.pages.len(), 1);
+    }
+
+    #[test]
+    fn test_empty_paginate_path_falls_back_to_default() {
+        let pages: Vec<PathBuf> = (0..3).map(|i| PathBuf::from(format!("{}.md", i))).collect();
+
+        for paginate_path in ["", "/"] {
+            let paginator = Paginator::new(&pages, 2, paginate_path, "/blog/", &config());
+            assert_eq!(paginator.paginate_path, "page");
+            assert_eq!(paginator.pagers[1].permalink, "https://example.com/blog/page/2/");
+        }
+    }
+
+    #[test]
+    fn test_empty_paginator_has_one_pager() {
+        let paginator = Paginator::new(&[], 10, "page", "/blog/", &config());
+
+        assert_eq!(paginator.pagers.len(), 1);
+        assert!(paginator.pagers[0].pages.is_empty());
+    }
+}
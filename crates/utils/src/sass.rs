@@ -0,0 +1,80 @@
+use std::path::Path;
+
+use errors::{anyhow, Result};
+use libs::grass;
+use libs::walkdir::WalkDir;
+
+/// Compiles every `.scss` and `.sass` file of a directory into CSS
+///
+/// Partials, the files starting with `_`, are only compiled as part of the files importing
+/// them. Imports are looked up in the directory, then in `load_paths`, e.g. for a site to
+/// use the partials of its theme. Returns the path of the CSS files relative to the
+/// directory, e.g. `css/site.css` for `css/site.scss`, along with their content.
+pub fn compile_sass(sass_path: &Path, load_paths: &[&Path]) -> Result<Vec<(String, String)>> {
+    let options = load_paths
+        .iter()
+        .fold(grass::Options::default().load_path(sass_path), |options, path| options.load_path(path));
+
+    let mut files: Vec<_> = WalkDir::new(sass_path)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .filter(|path| {
+            let is_sass = matches!(path.extension().and_then(|ext| ext.to_str()), Some("scss" | "sass"));
+            let is_partial = path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with('_'));
+            is_sass && !is_partial
+        })
+        .collect();
+    files.sort();
+
+    let mut compiled = Vec::new();
+    for path in files {
+        let css = grass::from_path(&path, &options)
+            .map_err(|err| anyhow!("Failed to compile {}: {}", path.display(), err))?;
+        let relative_path = path.strip_prefix(sass_path)?.with_extension("css");
+        compiled.push((relative_path.to_string_lossy().replace('\\', "/"), css));
+    }
+
+    Ok(compiled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_sass() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("css")).unwrap();
+        std::fs::write(dir.path().join("_colors.scss"), "$primary: #123456;").unwrap();
+        std::fs::write(dir.path().join("css/site.scss"), "@use 'colors';\na { color: colors.$primary; }").unwrap();
+        std::fs::write(dir.path().join("print.sass"), "body\n  margin: 0").unwrap();
+
+        let compiled = compile_sass(dir.path(), &[]).unwrap();
+        let paths: Vec<_> = compiled.iter().map(|(path, _)| path.as_str()).collect();
+        assert_eq!(paths, vec!["css/site.css", "print.css"]);
+        assert!(compiled[0].1.contains("color: #123456;"));
+    }
+
+    #[test]
+    fn test_sass_errors_name_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("site.scss"), "a { color: ; ").unwrap();
+
+        let err = compile_sass(dir.path(), &[]).unwrap_err().to_string();
+        assert!(err.starts_with(&format!("Failed to compile {}", dir.path().join("site.scss").display())));
+    }
+
+    #[test]
+    fn test_imports_are_looked_up_in_load_paths() {
+        let site = tempfile::tempdir().unwrap();
+        let theme = tempfile::tempdir().unwrap();
+        std::fs::write(theme.path().join("_colors.scss"), "$primary: #123456;").unwrap();
+        std::fs::write(site.path().join("site.scss"), "@use 'colors';\na { color: colors.$primary; }").unwrap();
+
+        let compiled = compile_sass(site.path(), &[theme.path()]).unwrap();
+        assert!(compiled[0].1.contains("color: #123456;"));
+    }
+}
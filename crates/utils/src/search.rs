@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::content::Heading;
+use crate::page::Page;
+use crate::section::Section;
+
+/// Name of the search index file, at the root of the output
+pub const SEARCH_INDEX_FILE: &str = "search_index.json";
+
+static TAG_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>").unwrap());
+static WHITESPACE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s+").unwrap());
+
+/// The `[search]` section of the config
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    /// Most characters of the body of a page kept in the index, all of them when not set
+    pub truncate_content_length: Option<usize>,
+}
+
+/// A page or section of the search index
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SearchEntry<'a> {
+    pub url: &'a str,
+    pub title: Option<&'a str>,
+    pub description: Option<&'a str>,
+    /// Titles of every heading of the content, in the order they appear
+    pub headings: Vec<&'a str>,
+    /// The text of the content, without any markup
+    pub body: String,
+}
+
+/// The text of rendered HTML, with tags removed and whitespace collapsed
+pub fn html_to_text(html: &str) -> String {
+    let text = TAG_REGEX.replace_all(html, " ");
+    let text = WHITESPACE_REGEX.replace_all(&text, " ");
+    text.trim()
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        // last, not to decode `&amp;lt;` twice
+        .replace("&amp;", "&")
+}
+
+fn heading_titles<'a>(toc: &'a [Heading], titles: &mut Vec<&'a str>) {
+    for heading in toc {
+        titles.push(&heading.title);
+        heading_titles(&heading.children, titles);
+    }
+}
+
+fn entry<'a>(
+    url: &'a str,
+    title: Option<&'a str>,
+    description: Option<&'a str>,
+    toc: &'a [Heading],
+    content: &str,
+    config: &SearchConfig,
+) -> SearchEntry<'a> {
+    let mut headings = Vec::new();
+    heading_titles(toc, &mut headings);
+
+    let mut body = html_to_text(content);
+    if let Some(length) = config.truncate_content_length {
+        if let Some((index, _)) = body.char_indices().nth(length) {
+            body.truncate(index);
+        }
+    }
+
+    SearchEntry { url, title, description, headings, body }
+}
+
+/// Every rendered page and section of the site, sorted by URL
+///
+/// A section with `in_search_index = false` is left out along with the pages it directly
+/// holds. `parent_section` gives the `_index.md` of the section holding a page.
+pub fn build_index<'a, F>(
+    pages: &'a HashMap<PathBuf, Page>,
+    sections: &'a HashMap<PathBuf, Section>,
+    parent_section: F,
+    config: &SearchConfig,
+) -> Vec<SearchEntry<'a>>
+where
+    F: Fn(&Path) -> Option<PathBuf>,
+{
+    let is_indexed = |section: Option<PathBuf>| {
+        section.and_then(|path| sections.get(&path)).is_none_or(|section| section.meta.in_search_index)
+    };
+
+    let mut entries: Vec<SearchEntry> = pages
+        .iter()
+        .filter(|(path, _)| is_indexed(parent_section(path)))
+        .map(|(_, page)| {
+            entry(&page.permalink, page.meta.title.as_deref(), page.meta.description.as_deref(), &page.toc, &page.content, config)
+        })
+        .collect();
+
+    entries.extend(
+        sections
+            .values()
+            .filter(|section| section.meta.render && section.meta.in_search_index)
+            .map(|section| {
+                entry(
+                    &section.permalink,
+                    section.meta.title.as_deref(),
+                    section.meta.description.as_deref(),
+                    &section.toc,
+                    &section.content,
+                    config,
+                )
+            }),
+    );
+
+    entries.sort_by(|a, b| a.url.cmp(b.url));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text() {
+        assert_eq!(
+            html_to_text("<h2 id=\"a\">Setup</h2>\n<p>Run <code>a &amp;&amp; b</code>,\n  then &lt;done&gt;</p>"),
+            "Setup Run a && b , then <done>"
+        );
+        assert_eq!(html_to_text("<p>&amp;lt;</p>"), "&lt;");
+    }
+
+    #[test]
+    fn test_build_index() {
+        let mut guide = Page {
+            permalink: "https://example.com/docs/guide/".to_string(),
+            content: "<h2>Install</h2><p>Héllo world</p>".to_string(),
+            toc: vec![Heading {
+                title: "Install".to_string(),
+                children: vec![Heading { title: "Linux".to_string(), ..Heading::default() }],
+                ..Heading::default()
+            }],
+            ..Page::default()
+        };
+        guide.meta.title = Some("Guide".to_string());
+        let secret = Page { permalink: "https://example.com/secret/page/".to_string(), ..Page::default() };
+        let pages: HashMap<PathBuf, Page> =
+            [(PathBuf::from("docs/guide.md"), guide), (PathBuf::from("secret/page.md"), secret)].into_iter().collect();
+
+        let docs = Section { permalink: "https://example.com/docs/".to_string(), ..Section::default() };
+        let mut secret = Section { permalink: "https://example.com/secret/".to_string(), ..Section::default() };
+        secret.meta.in_search_index = false;
+        let sections: HashMap<PathBuf, Section> =
+            [(PathBuf::from("docs/_index.md"), docs), (PathBuf::from("secret/_index.md"), secret)].into_iter().collect();
+
+        let parent_section = |path: &Path| Some(path.parent().unwrap().join("_index.md"));
+        let config = SearchConfig { truncate_content_length: Some(10) };
+        let index = build_index(&pages, &sections, parent_section, &config);
+
+        assert_eq!(index.len(), 2);
+        assert_eq!(index[0].url, "https://example.com/docs/");
+        assert_eq!(
+            index[1],
+            SearchEntry {
+                url: "https://example.com/docs/guide/",
+                title: Some("Guide"),
+                description: None,
+                headings: vec!["Install", "Linux"],
+                body: "Install Hé".to_string(),
+            }
+        );
+    }
+}
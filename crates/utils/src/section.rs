@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use anyhow::Result;
+use libs::slug::slugify;
+use libs::toml::{Table, Value};
+use serde::{Deserialize, Serialize};
+
+use crate::content::{Heading, InternalLink};
+use crate::fs::read_file;
+use crate::page::{
+    lines_before, move_unknown_keys_to_extra, parent_components, parse_front_matter, Page, SerializedPage,
+};
+use crate::site::Config;
+
+pub const DEFAULT_PAGINATE_PATH: &str = "page";
+
+/// How the pages of a section are sorted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortBy {
+    /// Most recent first, using the `date` of the pages
+    Date,
+    /// Most recently updated first, using `updated` and falling back to `date`
+    Updated,
+    /// Alphabetical order of the titles
+    Title,
+    /// Lightest first, using the `weight` of the pages
+    Weight,
+    /// Order in which the files were found
+    #[default]
+    None,
+}
+
+/// The front matter of a section, found in its `_index.md` file
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SectionFrontMatter {
+    pub title: Option<String>,
+
+    pub description: Option<String>,
+
+    /// How the pages of the section are sorted
+    pub sort_by: SortBy,
+
+    /// Used to sort the section among its siblings
+    pub weight: usize,
+
+    /// Number of pages per pager, no pagination when not set
+    pub paginate_by: Option<usize>,
+
+    /// Path segment before the pager index, e.g. `page` in `/blog/page/2/`, `page` when empty
+    pub paginate_path: String,
+
+    /// Template used to render the section instead of `section.html`
+    pub template: Option<String>,
+
+    /// Whether the pages of the section are also given to its parent section
+    pub transparent: bool,
+
+    /// Whether the section gets rendered, its pages are rendered either way
+    pub render: bool,
+
+    /// Whether feeds of the pages of the section are generated, see `feed_filenames`
+    pub generate_feeds: bool,
+
+    /// Whether the section and its pages are part of the search index, see `build_search_index`
+    pub in_search_index: bool,
+
+    /// Any extra data the templates might need
+    pub extra: Table,
+
+    /// Keys of the front matter that aren't fields, moved to `extra` once parsed
+    #[serde(flatten)]
+    unknown: HashMap<String, Option<Value>>,
+}
+
+impl Default for SectionFrontMatter {
+    fn default() -> Self {
+        Self {
+            title: None,
+            description: None,
+            sort_by: SortBy::None,
+            weight: 0,
+            paginate_by: None,
+            paginate_path: DEFAULT_PAGINATE_PATH.to_string(),
+            template: None,
+            transparent: false,
+            render: true,
+            generate_feeds: false,
+            in_search_index: true,
+            extra: Table::new(),
+            unknown: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Section {
+    pub file: String,
+    pub meta: SectionFrontMatter,
+    /// Path of the `_index.md` file relative to the content directory
+    pub relative_path: String,
+    /// The markdown content, without the front matter
+    pub raw_content: String,
+    /// Number of lines before `raw_content` in the file, i.e. the front matter
+    pub body_line_offset: usize,
+    /// The HTML rendered from `raw_content`
+    pub content: String,
+    /// Headings of `content`, nested by level
+    pub toc: Vec<Heading>,
+    /// Links to other pages and anchors found in `raw_content`
+    pub internal_links: Vec<InternalLink>,
+    /// Links to other websites found in `raw_content`
+    pub external_links: Vec<String>,
+    /// Directories between the content directory and the section
+    pub components: Vec<String>,
+    /// URL path of the section, e.g. `/blog/`
+    pub path: String,
+    pub permalink: String,
+    /// File paths of the pages of the section, sorted according to `sort_by`
+    pub pages: Vec<PathBuf>,
+    /// File paths of the `_index.md` of the direct subsections
+    pub subsections: Vec<PathBuf>,
+}
+
+/// Serialized version of a section for template rendering
+#[derive(Debug, Serialize)]
+pub struct SerializedSection<'a> {
+    title: Option<&'a str>,
+    description: Option<&'a str>,
+    relative_path: &'a str,
+    content: &'a str,
+    toc: &'a [Heading],
+    components: &'a [String],
+    path: &'a str,
+    permalink: &'a str,
+    extra: &'a Table,
+    pages: Vec<SerializedPage<'a>>,
+    subsections: Vec<SerializedSection<'a>>,
+}
+
+/// Sorts paths by the given key, paths without a key go last
+pub(crate) fn sort_by_key<K: Ord>(paths: &mut [PathBuf], descending: bool, key: impl Fn(&PathBuf) -> Option<K>) {
+    paths.sort_by(|a, b| match (key(a), key(b)) {
+        (Some(a), Some(b)) if descending => b.cmp(&a),
+        (Some(a), Some(b)) => a.cmp(&b),
+        (a, b) => b.is_some().cmp(&a.is_some()),
+    });
+}
+
+impl Section {
+    /// Create a new section from its `_index.md` file
+    pub fn new<P: AsRef<Path>>(file_path: P, base_path: &Path) -> Section {
+        let file_path = file_path.as_ref();
+        let relative_path = file_path
+            .strip_prefix(base_path.join("content"))
+            .unwrap_or(file_path);
+
+        Section {
+            file: file_path.display().to_string(),
+            relative_path: relative_path.to_string_lossy().replace('\\', "/"),
+            components: parent_components(relative_path),
+            ..Self::default()
+        }
+    }
+
+    fn parse(file_path: &Path, content: &str, config: &Config, base_path: &Path) -> Result<Section> {
+        let (front_matter, body) = Page::split_page_content(file_path, content)?;
+        let mut section = Self::new(file_path, base_path);
+
+        section.meta = parse_front_matter(file_path, &front_matter)?;
+        move_unknown_keys_to_extra(&mut section.meta.unknown, &mut section.meta.extra);
+        section.raw_content = body.to_string();
+        section.body_line_offset = lines_before(content, body);
+        section.set_path(config);
+
+        Ok(section)
+    }
+
+    /// Read `_index.md` files
+    pub fn parse_file<P: AsRef<Path>>(file_path: P, config: &Config, base_path: &Path) -> Result<Section> {
+        let path = file_path.as_ref();
+        let content = read_file(path)?;
+
+        Self::parse(path, &content, config, base_path)
+    }
+
+    /// Creates the section of a directory without an `_index.md` file
+    pub fn default_for<P: AsRef<Path>>(file_path: P, config: &Config, base_path: &Path) -> Section {
+        let mut section = Self::new(file_path, base_path);
+        section.set_path(config);
+        section
+    }
+
+    /// Whether this is the section of the content directory itself
+    pub fn is_index(&self) -> bool {
+        self.components.is_empty()
+    }
+
+    /// Sets the URL path and permalink of the section from its slugified components
+    fn set_path(&mut self, config: &Config) {
+        let mut path = String::from("/");
+        for component in &self.components {
+            path.push_str(&slugify(component));
+            path.push('/');
+        }
+
+        self.permalink = config.make_permalink(&path);
+        self.path = path;
+    }
+
+    /// Sorts the pages of the section according to its `sort_by`
+    ///
+    /// Pages missing the value they are sorted by are put last.
+    pub fn sort_pages(&mut self, pages: &HashMap<PathBuf, Page>) {
+        // sorting is stable, this keeps ties in a predictable order
+        self.pages.sort();
+
+        match self.meta.sort_by {
+            SortBy::None => {}
+            // dates are ISO 8601 strings so they can be compared as such
+            SortBy::Date => sort_by_key(&mut self.pages, true, |path| pages[path].meta.date.as_deref()),
+            SortBy::Updated => sort_by_key(&mut self.pages, true, |path| {
+                let meta = &pages[path].meta;
+                meta.updated.as_deref().or(meta.date.as_deref())
+            }),
+            SortBy::Title => sort_by_key(&mut self.pages, false, |path| pages[path].meta.title.as_deref()),
+            SortBy::Weight => sort_by_key(&mut self.pages, false, |path| pages[path].meta.weight),
+        }
+    }
+
+    /// Serialize the section for template rendering, along with its pages and subsections
+    pub fn serialize<'a>(
+        &'a self,
+        pages: &'a HashMap<PathBuf, Page>,
+        sections: &'a HashMap<PathBuf, Section>,
+    ) -> SerializedSection<'a> {
+        SerializedSection {
+            pages: self.pages.iter().map(|path| pages[path].serialize()).collect(),
+            subsections: self
+                .subsections
+                .iter()
+                .map(|path| sections[path].serialize(pages, sections))
+                .collect(),
+            ..self.serialize_basic()
+        }
+    }
+
+    /// Serialize the section without its pages and subsections
+    pub fn serialize_basic(&self) -> SerializedSection<'_> {
+        SerializedSection {
+            title: self.meta.title.as_deref(),
+            description: self.meta.description.as_deref(),
+            relative_path: &self.relative_path,
+            content: &self.content,
+            toc: &self.toc,
+            components: &self.components,
+            path: &self.path,
+            permalink: &self.permalink,
+            extra: &self.meta.extra,
+            pages: Vec::new(),
+            subsections: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::FrontMatter;
+
+    fn page_with(date: Option<&str>, weight: Option<usize>) -> Page {
+        let mut page = Page::default();
+        page.meta.date = date.map(str::to_string);
+        page.meta.weight = weight;
+        page
+    }
+
+    #[test]
+    fn test_section_front_matter_defaults() {
+        let meta: SectionFrontMatter =
+            parse_front_matter(Path::new("_index.md"), &FrontMatter::Toml("sort_by = \"weight\"")).unwrap();
+
+        assert_eq!(meta.sort_by, SortBy::Weight);
+        assert!(meta.render);
+        assert!(!meta.transparent);
+        assert_eq!(meta.paginate_by, None);
+    }
+
+    #[test]
+    fn test_sort_pages() {
+        let pages: HashMap<PathBuf, Page> = [
+            (PathBuf::from("a.md"), page_with(Some("2020-01-01"), Some(3))),
+            (PathBuf::from("b.md"), page_with(None, Some(1))),
+            (PathBuf::from("c.md"), page_with(Some("2023-05-01"), None)),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut section = Section {
+            pages: vec![PathBuf::from("b.md"), PathBuf::from("c.md"), PathBuf::from("a.md")],
+            ..Section::default()
+        };
+
+        section.meta.sort_by = SortBy::Date;
+        section.sort_pages(&pages);
+        assert_eq!(section.pages, vec![PathBuf::from("c.md"), PathBuf::from("a.md"), PathBuf::from("b.md")]);
+
+        section.meta.sort_by = SortBy::Weight;
+        section.sort_pages(&pages);
+        assert_eq!(section.pages, vec![PathBuf::from("b.md"), PathBuf::from("a.md"), PathBuf::from("c.md")]);
+    }
+}
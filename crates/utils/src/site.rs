@@ -1,17 +1,53 @@
-use std::{collections::HashSet, path::{Path, PathBuf}};
+use std::{collections::HashMap, path::{Path, PathBuf}, sync::{Arc, RwLock}};
 use libs::walkdir::WalkDir;
-use serde::Deserialize;
-use libs::toml;
-use errors::{ Result, bail };
+use libs::tera::{Context as TeraContext, Tera};
+use serde::{Deserialize, Serialize};
+use libs::toml::{self, Table};
+use libs::serde_json;
+use errors::{ Context, Result, bail };
 
-use crate::fs::{ copy_directory, read_file};
+use crate::fs::{ copy_directory, copy_file, create_file, read_file};
 use crate::page::{ Page };
+use crate::section::Section;
+use crate::taxonomies::{find_taxonomies, Taxonomy, TaxonomyConfig, TaxonomyTerm};
+use crate::pagination::{Paginator, SerializedPaginator};
+use crate::templates::{load_tera, render_redirect_template};
+use crate::link_checking::{self, LinkCheckerConfig};
+use crate::sitemap::{self, MAX_SITEMAP_ENTRIES};
+use crate::feeds::{feed_pages, FeedContent};
+use crate::search::{self, SearchConfig, SEARCH_INDEX_FILE};
+use crate::sass;
+use crate::theme::{merge_defaults, Theme};
 
 
 
 const DEFAULT_BASE_URL: &str = "http://localhost:8080";
+const DEFAULT_OUTPUT_DIR: &str = "public";
 
-#[derive(Debug, Clone, Deserialize)]
+/// Splits a URL path into the directories it is written to
+fn path_components(path: &str) -> Vec<String> {
+    path.split('/').filter(|xm| !xm.is_empty()).map(String::from).collect()
+}
+
+/// Files of a site built in memory, keyed by the path they are served at,
+/// e.g. `/blog/post/index.html`
+pub type MemoryOutput = Arc<RwLock<HashMap<String, Vec<u8>>>>;
+
+/// The path a file of the static directory is served at
+fn static_url_path(static_path: &Path, path: &Path) -> Result<String> {
+    let relative_path = path.strip_prefix(static_path)?;
+    Ok(format!("/{}", relative_path.to_string_lossy().replace('\\', "/")))
+}
+
+fn default_output_dir() -> String {
+    DEFAULT_OUTPUT_DIR.to_string()
+}
+
+fn default_feed_filenames() -> Vec<String> {
+    vec![String::from("rss.xml")]
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Config {
     /// Base URL of the site, the only required config argument
     pub base_url: String,
@@ -25,7 +61,48 @@ pub struct Config {
     /// Description of the site
     pub description: Option<String>,
 
+    /// Directory the site is built into. Defaults to `public`
+    #[serde(default = "default_output_dir")]
     pub output_dir: String,
+
+    /// Whether feeds of all the dated pages of the site are generated at its root
+    #[serde(default)]
+    pub generate_feeds: bool,
+
+    /// Feeds generated, each rendered with the template of the same name.
+    /// `rss.xml` and `atom.xml` are built in. Defaults to `["rss.xml"]`
+    #[serde(default = "default_feed_filenames")]
+    pub feed_filenames: Vec<String>,
+
+    /// Most pages a feed has, all of them when not set
+    pub feed_limit: Option<usize>,
+
+    /// Whether feed entries have the full content of pages or only their summary
+    #[serde(default)]
+    pub feed_content: FeedContent,
+
+    /// Taxonomies pages can be classified with, declared with `[[taxonomies]]`
+    #[serde(default)]
+    pub taxonomies: Vec<TaxonomyConfig>,
+
+    /// Whether a JSON index of the content is generated for client-side search
+    #[serde(default)]
+    pub build_search_index: bool,
+
+    /// How the search index is built
+    #[serde(default)]
+    pub search: SearchConfig,
+
+    /// How external links are checked by `genetics check --external`
+    #[serde(default)]
+    pub link_checker: LinkCheckerConfig,
+
+    /// Theme of the site, found in `themes/<name>/`
+    pub theme: Option<String>,
+
+    /// Any extra data the templates might need, completed with the defaults of the theme
+    #[serde(default)]
+    pub extra: Table,
 }
 
 impl Config {
@@ -57,6 +134,32 @@ impl Config {
     pub fn get_config(filename: &Path) -> Result<Config> {
         Config::from_config_file(filename)
     }
+
+    /// The path the site is deployed under, `/docs/` for `https://example.com/docs`
+    pub fn base_path(&self) -> String {
+        let path = match libs::url::Url::parse(&self.base_url) {
+            Ok(url) => url.path().trim_matches('/').to_string(),
+            Err(_) => String::new(),
+        };
+
+        if path.is_empty() {
+            String::from("/")
+        } else {
+            format!("/{}/", path)
+        }
+    }
+
+    /// Makes a full URL from a path relative to the site root
+    pub fn make_permalink(&self, path: &str) -> String {
+        let base_url = self.base_url.trim_end_matches('/');
+        let path = path.trim_start_matches('/');
+
+        if path.is_empty() {
+            format!("{}/", base_url)
+        } else {
+            format!("{}/{}", base_url, path)
+        }
+    }
 }
 
 
@@ -72,6 +175,34 @@ pub struct Site {
     pub output_path: PathBuf, 
 
     pub static_path: PathBuf,
+
+    pub content_path: PathBuf,
+
+    pub templates_path: PathBuf,
+
+    pub sass_path: PathBuf,
+
+    /// Directory of the theme of the site, if it has one
+    pub theme_path: Option<PathBuf>,
+
+    /// All the pages of the site, keyed by their file path
+    pub pages: HashMap<PathBuf, Page>,
+
+    /// All the sections of the site, keyed by the file path of their `_index.md`
+    pub sections: HashMap<PathBuf, Section>,
+
+    pub taxonomies: Vec<Taxonomy>,
+
+    /// Permalinks of every page and section, keyed by their path relative to the content directory
+    pub permalinks: HashMap<String, String>,
+
+    /// Whether pages with `draft = true` are part of the site
+    pub include_drafts: bool,
+
+    /// Where the output goes when it isn't written to `output_path`
+    pub memory_output: Option<MemoryOutput>,
+
+    pub tera: Tera,
 }
 
 
@@ -79,21 +210,38 @@ pub struct Site {
 impl Site {
     pub fn new<P: AsRef<Path>, P2: AsRef<Path>>(path: P, config_file: P2) -> Result<Site> {
         let path = path.as_ref(); 
-        let config = Config::get_config(&path.join(config_file))?;
+        let mut config = Config::get_config(&path.join(config_file))?;
         let output_path = path.join(config.output_dir.clone());
         let static_path = path.join("static");
+        let content_path = path.join("content");
+        let templates_path = path.join("templates");
+        let sass_path = path.join("sass");
 
+        let theme_path = config.theme.as_ref().map(|theme| path.join("themes").join(theme));
+        if let Some(theme_path) = &theme_path {
+            let theme = Theme::from_directory(theme_path)?;
+            merge_defaults(&mut config.extra, &theme.extra);
+        }
 
-        // let content_path = path.join("content");
-        // let template_path = path.join("templates");
-        // let static_path = path.join("static");
-
+        let theme_templates_path = theme_path.as_ref().map(|theme_path| theme_path.join("templates"));
+        let tera = load_tera(&templates_path, theme_templates_path.as_deref())?;
 
         let site = Site{
             base_path: path.to_path_buf(),
             config,
             output_path,
             static_path,
+            content_path,
+            templates_path,
+            sass_path,
+            theme_path,
+            pages: HashMap::new(),
+            sections: HashMap::new(),
+            taxonomies: Vec::new(),
+            permalinks: HashMap::new(),
+            include_drafts: false,
+            memory_output: None,
+            tera,
         };
 
 
@@ -102,10 +250,10 @@ impl Site {
 
     /// Loads all files(markdown, templates, static) from the site
     pub fn load_files(&mut self) -> Result<()> {
-        let mut walkdir = WalkDir::new(self.base_path.join("content")).follow_links(true).into_iter(); 
+        let mut walkdir = WalkDir::new(&self.content_path).follow_links(true).into_iter(); 
 
         let mut pages = Vec::new();
-        //let mut sections: HashSet<String> = HashSet::new();
+        let mut sections = Vec::new();
 
         loop {
             let entry = match walkdir.next() {
@@ -120,60 +268,450 @@ impl Site {
                 Some(name) => name.to_str().unwrap(),
             };
 
-            if file_name.starts_with(".") {
-                continue;
-            }
-
             // skip hidden and non .md files in the directory
-            if !path.is_dir() && (!file_name.ends_with(".md")) || file_name.starts_with('.') {
+            if path.is_dir() || !file_name.ends_with(".md") || file_name.starts_with('.') {
                 continue; 
             }
 
-            if path.is_dir() {
-                // let index_files = WalkDir::new(path)
-                //     .follow_links(true)
-                //     .max_depth(1)
-                //     .into_iter()
-                //     .filter_map(|e| match e {
-                //         Err(_) => None,
-                //         Ok(f) => {
-                //             if f.path().is_file() {
-                //                 Some(f)
-                //             }
-                //             else {
-                //                 None
-                //             }
-                //         }
-                //     })
-                //     .collect::<Vec<walkdir::DirEntry>>();
-
-
-                //add sections here
-                // for index in index_files {
-                //     let path = index.path(); 
-                    
-                //    // self.add_section(path.display().to_string());
-                // }
+            if file_name == "_index.md" {
+                sections.push(Section::parse_file(path, &self.config, &self.base_path)?);
             }
             else {
-
-                //add pages here
-                let page = Page::parse_file(path, &self.config, &self.base_path);
-                pages.push(page); 
+                let page = Page::parse_file(path, &self.config, &self.base_path)?;
+                // drafts never make it into sections, taxonomies or feeds unless asked for
+                if !page.meta.draft || self.include_drafts {
+                    pages.push(page);
+                }
             }
         }
 
+        self.pages = pages
+            .into_iter()
+            .map(|page| (PathBuf::from(&page.file), page))
+            .collect();
+        self.sections = HashMap::new();
+        for section in sections {
+            self.add_section(section);
+        }
+
+        // the index section always exists, even without a `content/_index.md`
+        let index_path = self.content_path.join("_index.md");
+        if !self.sections.contains_key(&index_path) {
+            self.add_section(Section::default_for(&index_path, &self.config, &self.base_path));
+        }
+
+        self.populate_sections();
+        self.permalinks = self
+            .pages
+            .values()
+            .map(|page| (page.relative_path.clone(), page.permalink.clone()))
+            .chain(
+                self.sections
+                    .values()
+                    .map(|section| (section.relative_path.clone(), section.permalink.clone())),
+            )
+            .collect();
+        self.taxonomies = find_taxonomies(&self.config, &self.pages)?;
+
         Ok(())
     }
 
     /// Add a section to the site
-    /// TODO
-    pub fn add_section(&mut self, _section: HashSet<String>) -> Result<()> {
+    pub fn add_section(&mut self, section: Section) {
+        self.sections.insert(PathBuf::from(&section.file), section);
+    }
+
+    /// Finds the closest section owning the given file, excluding the file itself
+    fn find_parent_section(&self, file: &Path) -> Option<PathBuf> {
+        file.ancestors()
+            .skip(1)
+            .take_while(|dir| dir.starts_with(&self.content_path))
+            .map(|dir| dir.join("_index.md"))
+            .find(|index| index != file && self.sections.contains_key(index))
+    }
+
+    /// Gives every section its pages and subsections
+    ///
+    /// Pages of a transparent section are also given to its parent, recursively.
+    fn populate_sections(&mut self) {
+        for section in self.sections.values_mut() {
+            section.pages.clear();
+            section.subsections.clear();
+        }
+
+        let mut page_paths: Vec<PathBuf> = self.pages.keys().cloned().collect();
+        page_paths.sort();
+        for page_path in page_paths {
+            let mut parent = self.find_parent_section(&page_path);
+            while let Some(section_path) = parent {
+                let section = self.sections.get_mut(&section_path).unwrap();
+                section.pages.push(page_path.clone());
+
+                parent = if section.meta.transparent {
+                    self.find_parent_section(&section_path)
+                } else {
+                    None
+                };
+            }
+        }
 
-        
+        let mut section_paths: Vec<PathBuf> = self.sections.keys().cloned().collect();
+        section_paths.sort();
+        for section_path in section_paths {
+            if let Some(parent) = self.find_parent_section(&section_path) {
+                self.sections.get_mut(&parent).unwrap().subsections.push(section_path);
+            }
+        }
+
+        let weights: HashMap<PathBuf, usize> = self
+            .sections
+            .iter()
+            .map(|(path, section)| (path.clone(), section.meta.weight))
+            .collect();
+        for section in self.sections.values_mut() {
+            section.sort_pages(&self.pages);
+            // sorting is stable, subsections of the same weight stay sorted by path
+            section.subsections.sort_by_key(|path| weights[path]);
+        }
+    }
+
+    /// Renders a single page with its template
+    pub fn render_page(&self, page: &Page) -> Result<String> {
+        let mut context = TeraContext::new();
+        context.insert("config", &self.config);
+        context.insert("page", &page.serialize());
+        context.insert("current_url", &page.permalink);
+        context.insert("current_path", &page.path);
+
+        let template = page.meta.template.as_deref().unwrap_or("page.html");
+        self.tera
+            .render(template, &context)
+            .with_context(|| format!("Failed to render page {}", page.file))
+    }
+
+    /// Renders a single section with its template
+    ///
+    /// The index section uses `index.html`, every other section `section.html`.
+    pub fn render_section(&self, section: &Section, paginator: Option<&SerializedPaginator>) -> Result<String> {
+        let mut context = TeraContext::new();
+        context.insert("config", &self.config);
+        context.insert("section", &section.serialize(&self.pages, &self.sections));
+        context.insert("current_url", &section.permalink);
+        context.insert("current_path", &section.path);
+        if let Some(paginator) = paginator {
+            context.insert("paginator", paginator);
+        }
+
+        let default_template = if section.is_index() { "index.html" } else { "section.html" };
+        let template = section.meta.template.as_deref().unwrap_or(default_template);
+        self.tera
+            .render(template, &context)
+            .with_context(|| format!("Failed to render section {}", section.file))
+    }
+
+    /// Renders the list of all the terms of a taxonomy with `taxonomy_list.html`
+    pub fn render_taxonomy_list(&self, taxonomy: &Taxonomy) -> Result<String> {
+        let terms: Vec<_> = taxonomy.items.iter().map(|term| term.serialize(&self.pages)).collect();
+
+        let mut context = TeraContext::new();
+        context.insert("config", &self.config);
+        context.insert("taxonomy", &taxonomy.serialize());
+        context.insert("terms", &terms);
+        context.insert("current_url", &taxonomy.permalink);
+        context.insert("current_path", &taxonomy.path);
+
+        self.tera
+            .render("taxonomy_list.html", &context)
+            .with_context(|| format!("Failed to render the list of taxonomy `{}`", taxonomy.kind.name))
+    }
+
+    /// Renders a single taxonomy term and its pages with `taxonomy_single.html`
+    pub fn render_taxonomy_term(
+        &self,
+        taxonomy: &Taxonomy,
+        term: &TaxonomyTerm,
+        paginator: Option<&SerializedPaginator>,
+    ) -> Result<String> {
+        let mut context = TeraContext::new();
+        context.insert("config", &self.config);
+        context.insert("taxonomy", &taxonomy.serialize());
+        context.insert("term", &term.serialize(&self.pages));
+        context.insert("current_url", &term.permalink);
+        context.insert("current_path", &term.path);
+        if let Some(paginator) = paginator {
+            context.insert("paginator", paginator);
+        }
+
+        self.tera
+            .render("taxonomy_single.html", &context)
+            .with_context(|| format!("Failed to render term `{}` of taxonomy `{}`", term.name, taxonomy.kind.name))
+    }
+
+    /// Renders a feed of the given pages with the template named like the feed, e.g. `atom.xml`
+    ///
+    /// Only the dated pages are part of the feed, see [`feed_pages`]. `permalink` is the
+    /// one of what the feed is about, the site, a section or a term, and `context` holds
+    /// what the template needs to know about it.
+    pub fn render_feed<'a>(
+        &'a self,
+        pages: impl IntoIterator<Item = &'a PathBuf>,
+        feed_filename: &str,
+        permalink: &str,
+        mut context: TeraContext,
+    ) -> Result<String> {
+        let pages = feed_pages(&self.pages, pages, self.config.feed_limit);
+        let last_updated = pages.iter().filter_map(|page| page.last_updated()).max();
+        let pages: Vec<_> = pages.iter().map(|page| page.serialize()).collect();
+
+        context.insert("config", &self.config);
+        context.insert("pages", &pages);
+        context.insert("last_updated", &last_updated);
+        context.insert("permalink", permalink);
+        context.insert("feed_url", &format!("{}{}", permalink, feed_filename));
+
+        self.tera
+            .render(feed_filename, &context)
+            .with_context(|| format!("Failed to render the feed {}", feed_filename))
+    }
+
+    /// Renders and writes every feed of `feed_filenames` under the given path components
+    fn write_feeds<'a, F>(&'a self, pages: F, components: &[String], permalink: &str, context: &TeraContext) -> Result<()>
+    where
+        F: IntoIterator<Item = &'a PathBuf> + Clone,
+    {
+        for feed_filename in &self.config.feed_filenames {
+            let output = self.render_feed(pages.clone(), feed_filename, permalink, context.clone())?;
+            self.write_content(components, feed_filename, &output)?;
+        }
+        Ok(())
+    }
+
+    /// Renders and writes `robots.txt` and `404.html` at the root of the output
+    ///
+    /// Both have a built-in template used when the site doesn't have its own.
+    pub fn write_special_pages(&self) -> Result<()> {
+        for filename in ["robots.txt", "404.html"] {
+            let mut context = TeraContext::new();
+            context.insert("config", &self.config);
+            context.insert("current_url", &self.config.make_permalink(filename));
+            context.insert("current_path", &format!("/{}", filename));
+
+            let output = self
+                .tera
+                .render(filename, &context)
+                .with_context(|| format!("Failed to render {}", filename))?;
+            self.write_content(&[], filename, &output)?;
+        }
         Ok(())
     }
 
+    /// Compiles the sass of the theme, then of the site, into CSS files at the same path
+    ///
+    /// The sass of the site can import the partials of the theme.
+    pub fn compile_sass(&self) -> Result<()> {
+        let theme_sass_path = self
+            .theme_path
+            .as_ref()
+            .map(|theme_path| theme_path.join("sass"))
+            .filter(|path| path.exists());
+
+        if let Some(theme_sass_path) = &theme_sass_path {
+            self.write_sass(theme_sass_path, &[])?;
+        }
+        if self.sass_path.exists() {
+            let load_paths: Vec<&Path> = theme_sass_path.iter().map(PathBuf::as_path).collect();
+            self.write_sass(&self.sass_path, &load_paths)?;
+        }
+        Ok(())
+    }
+
+    /// Compiles the sass of a directory and writes the CSS files, see [`sass::compile_sass`]
+    fn write_sass(&self, sass_path: &Path, load_paths: &[&Path]) -> Result<()> {
+        for (path, css) in sass::compile_sass(sass_path, load_paths)? {
+            let mut components = path_components(&path);
+            let filename = components.pop().unwrap_or_default();
+            self.write_content(&components, &filename, &css)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the search index of the rendered pages and sections as JSON
+    pub fn write_search_index(&self) -> Result<()> {
+        let index = search::build_index(
+            &self.pages,
+            &self.sections,
+            |path| self.find_parent_section(path),
+            &self.config.search,
+        );
+        let output = serde_json::to_string(&index).context("Failed to serialize the search index")?;
+        self.write_content(&[], SEARCH_INDEX_FILE, &output)
+    }
+
+    /// Renders and writes `sitemap.xml` with the `sitemap.xml` template
+    ///
+    /// Past [`MAX_SITEMAP_ENTRIES`], the entries are split into `sitemap1.xml`, `sitemap2.xml`...
+    /// and `sitemap.xml` lists them with the `split_sitemap_index.xml` template instead.
+    pub fn write_sitemap(&self) -> Result<()> {
+        let entries = sitemap::find_entries(&self.pages, &self.sections, &self.taxonomies);
+
+        let render = |entries: &[sitemap::SitemapEntry]| {
+            let mut context = TeraContext::new();
+            context.insert("config", &self.config);
+            context.insert("entries", entries);
+            self.tera.render("sitemap.xml", &context).context("Failed to render the sitemap")
+        };
+
+        if entries.len() <= MAX_SITEMAP_ENTRIES {
+            return self.write_content(&[], "sitemap.xml", &render(&entries)?);
+        }
+
+        let mut sitemaps = Vec::new();
+        for (i, chunk) in entries.chunks(MAX_SITEMAP_ENTRIES).enumerate() {
+            let filename = format!("sitemap{}.xml", i + 1);
+            self.write_content(&[], &filename, &render(chunk)?)?;
+            sitemaps.push(self.config.make_permalink(&filename));
+        }
+
+        let mut context = TeraContext::new();
+        context.insert("config", &self.config);
+        context.insert("sitemaps", &sitemaps);
+        let output = self
+            .tera
+            .render("split_sitemap_index.xml", &context)
+            .context("Failed to render the sitemap index")?;
+        self.write_content(&[], "sitemap.xml", &output)
+    }
+
+    /// Renders and writes every pager of a paginator
+    ///
+    /// The first pager lives at the URL of what is paginated, its numbered URL redirects there.
+    fn write_paginated<F>(&self, paginator: &Paginator, render: F) -> Result<()>
+    where
+        F: Fn(&SerializedPaginator) -> Result<String>,
+    {
+        for pager in &paginator.pagers {
+            let output = render(&paginator.serialize(pager, &self.pages))?;
+            self.write_content(&path_components(&pager.path), "index.html", &output)?;
+        }
+
+        let first_pager = format!("{}{}/1/", paginator.path, paginator.paginate_path);
+        self.write_content(
+            &path_components(&first_pager),
+            "index.html",
+            &render_redirect_template(&paginator.permalink),
+        )?;
+
+        Ok(())
+    }
+
+    /// Writes a rendered file into the output directory, under the given path components
+    pub fn write_content(&self, components: &[String], filename: &str, content: &str) -> Result<()> {
+        if let Some(memory_output) = &self.memory_output {
+            let mut url_path = String::from("/");
+            for component in components {
+                url_path.push_str(component);
+                url_path.push('/');
+            }
+            url_path.push_str(filename);
+
+            memory_output.write().unwrap().insert(url_path, content.as_bytes().to_vec());
+            return Ok(());
+        }
+
+        let mut path = self.output_path.clone();
+        for component in components {
+            path.push(component);
+        }
+        path.push(filename);
+
+        create_file(&path, content)
+    }
+
+    /// Publishes files built into another memory output to the output of the site, its
+    /// memory output or its output directory
+    ///
+    /// Lets a rebuild happen in a staging output, only published once it succeeded for the
+    /// last good build to stay in place otherwise. The files of a `full_build` replace
+    /// the whole memory output, for removed pages not to be served anymore.
+    pub fn publish_output(&self, files: HashMap<String, Vec<u8>>, full_build: bool) -> Result<()> {
+        if let Some(memory_output) = &self.memory_output {
+            let mut output = memory_output.write().unwrap();
+            if full_build {
+                *output = files;
+            } else {
+                output.extend(files);
+            }
+            return Ok(());
+        }
+
+        for (url_path, content) in files {
+            let path = self.output_path.join(url_path.trim_start_matches('/'));
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create directory {}", parent.display()))?;
+            }
+            std::fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Static directories of the site, the one of the theme first for the site to override its files
+    fn static_paths(&self) -> Vec<PathBuf> {
+        let mut static_paths: Vec<PathBuf> =
+            self.theme_path.iter().map(|theme_path| theme_path.join("static")).collect();
+        static_paths.push(self.static_path.clone());
+        static_paths
+    }
+
+    /// The path a file of one of the static directories is served at, e.g. `/css/site.css`
+    pub fn static_url(&self, path: &Path) -> Option<String> {
+        self.static_paths().iter().find_map(|static_path| static_url_path(static_path, path).ok())
+    }
+
+    /// Writes a file of a static directory to the output
+    fn write_static_file(&self, static_path: &Path, path: &Path) -> Result<()> {
+        match &self.memory_output {
+            Some(memory_output) => {
+                let content = std::fs::read(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                memory_output.write().unwrap().insert(static_url_path(static_path, path)?, content);
+                Ok(())
+            }
+            None => copy_file(path, &self.output_path, static_path),
+        }
+    }
+
+    /// Fails listing every internal link of the site pointing to a missing page or anchor
+    ///
+    /// Must be called once the markdown of the site has been rendered.
+    pub fn check_internal_links(&self) -> Result<()> {
+        link_checking::check_internal_links(&self.pages, &self.sections)
+    }
+
+    /// Keeps the output in memory instead of writing it to the output directory
+    pub fn write_to_memory(&mut self, memory_output: MemoryOutput) {
+        self.memory_output = Some(memory_output);
+    }
+
+    /// Fails listing every external link of the site that can't be reached
+    ///
+    /// Must be called once the markdown of the site has been rendered. Links found working
+    /// recently are remembered in the `cache_file` of the `[link_checker]` config.
+    pub fn check_external_links(&self) -> Result<()> {
+        let cache_path = self.base_path.join(&self.config.link_checker.cache_file);
+        link_checking::check_external_links(
+            &self.pages,
+            &self.sections,
+            &self.config.link_checker,
+            Some(&cache_path),
+        )
+    }
+
+    /// Makes draft pages part of the site, must be called before loading the files
+    pub fn include_drafts(&mut self) {
+        self.include_drafts = true;
+    }
+
     pub fn set_base_url(&mut self, base_url: String) {
         self.config.base_url = base_url;
     }
@@ -183,52 +721,328 @@ impl Site {
         self.output_path = path.as_ref().to_path_buf();
     }
 
+    /// Copies the static files of the theme, then of the site, to the output
     pub fn copy_static_directories(&self) -> Result<()>{
-        if self.static_path.exists() {
-            println!("Copying static files from {} to {}", 
-                self.static_path.display(), 
-                self.output_path.display()
-            );
-            copy_directory(&self.static_path, &self.output_path)?;
-        } else {
+        let static_paths: Vec<PathBuf> = self.static_paths().into_iter().filter(|path| path.exists()).collect();
+        if static_paths.is_empty() {
             println!("No static directory found at {}", self.static_path.display());
         }
+
+        for static_path in static_paths {
+            if self.memory_output.is_some() {
+                let files = WalkDir::new(&static_path)
+                    .follow_links(true)
+                    .into_iter()
+                    .filter_map(std::result::Result::ok)
+                    .filter(|entry| entry.file_type().is_file());
+                for entry in files {
+                    self.write_static_file(&static_path, entry.path())?;
+                }
+            } else {
+                println!("Copying static files from {} to {}", 
+                    static_path.display(), 
+                    self.output_path.display()
+                );
+                copy_directory(&static_path, &self.output_path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Re-reads a page after its file changed
+    ///
+    /// Returns `false` without touching the site when the change can affect other pages,
+    /// i.e. the file was added or removed or its front matter changed, in which case all
+    /// the files have to be loaded again.
+    pub fn reload_page(&mut self, path: &Path) -> Result<bool> {
+        let old_meta = match self.pages.get(path) {
+            Some(page) if path.is_file() => &page.meta,
+            _ => return Ok(false),
+        };
+
+        let page = Page::parse_file(path, &self.config, &self.base_path)?;
+        if page.meta != *old_meta {
+            return Ok(false);
+        }
+
+        self.pages.insert(path.to_path_buf(), page);
+        Ok(true)
+    }
+
+    /// Reloads the templates after one of them changed
+    pub fn reload_templates(&mut self) -> Result<()> {
+        let theme_templates_path = self.theme_path.as_ref().map(|theme_path| theme_path.join("templates"));
+        self.tera = load_tera(&self.templates_path, theme_templates_path.as_deref())?;
+        Ok(())
+    }
+
+    /// Copies a single file of a static directory to the output, or removes its copy
+    /// when the file doesn't exist anymore
+    ///
+    /// The output keeps the file of the site over the one of the theme at the same path,
+    /// and falls back to the one of the theme when the file of the site is removed.
+    pub fn copy_static_file(&self, path: &Path) -> Result<()> {
+        let url_path = match self.static_url(path) {
+            Some(url_path) => url_path,
+            None => return Ok(()),
+        };
+        let relative_path = url_path.trim_start_matches('/');
+
+        let source = self
+            .static_paths()
+            .into_iter()
+            .rev()
+            .map(|static_path| {
+                let file = static_path.join(relative_path);
+                (static_path, file)
+            })
+            .find(|(_, file)| file.is_file());
+        if let Some((static_path, file)) = source {
+            return self.write_static_file(&static_path, &file);
+        }
+
+        if let Some(memory_output) = &self.memory_output {
+            memory_output.write().unwrap().remove(&url_path);
+            return Ok(());
+        }
+
+        let target_path = self.output_path.join(relative_path);
+        if target_path.is_file() {
+            std::fs::remove_file(&target_path)
+                .with_context(|| format!("Failed to remove {}", target_path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Renders a page with its template and writes it to the output directory
+    pub fn write_page(&self, page: &Page) -> Result<()> {
+        let output = self.render_page(page)?;
+        self.write_content(&path_components(&page.path), "index.html", &output)?;
+        Ok(())
+    }
+
+    /// Writes the feeds of the whole site, if it has any
+    fn write_site_feeds(&self) -> Result<()> {
+        if !self.config.generate_feeds {
+            return Ok(());
+        }
+        self.write_feeds(self.pages.keys(), &[], &self.config.make_permalink(""), &TeraContext::new())
+    }
+
+    /// Renders and writes a section, paginated if it has a `paginate_by`, and its feeds
+    fn write_section(&self, section: &Section) -> Result<()> {
+        if section.meta.generate_feeds {
+            let mut context = TeraContext::new();
+            context.insert("section", &section.serialize(&self.pages, &self.sections));
+            self.write_feeds(&section.pages, &path_components(&section.path), &section.permalink, &context)
+                .with_context(|| format!("Failed to write the feeds of section {}", section.file))?;
+        }
+
+        if !section.meta.render {
+            return Ok(());
+        }
+        match Paginator::from_section(section, &self.config) {
+            Some(paginator) => self.write_paginated(&paginator, |pager| self.render_section(section, Some(pager))),
+            None => {
+                let output = self.render_section(section, None)?;
+                self.write_content(&path_components(&section.path), "index.html", &output)
+            }
+        }
+    }
+
+    /// Renders and writes the list of the terms of a taxonomy
+    fn write_taxonomy_list(&self, taxonomy: &Taxonomy) -> Result<()> {
+        let output = self.render_taxonomy_list(taxonomy)?;
+        self.write_content(std::slice::from_ref(&taxonomy.slug), "index.html", &output)
+    }
+
+    /// Renders and writes a taxonomy term, paginated if its taxonomy has a `paginate_by`,
+    /// and its feeds
+    fn write_taxonomy_term(&self, taxonomy: &Taxonomy, term: &TaxonomyTerm) -> Result<()> {
+        let components = [taxonomy.slug.clone(), term.slug.clone()];
+        match Paginator::from_taxonomy_term(taxonomy, term, &self.config) {
+            Some(paginator) => {
+                self.write_paginated(&paginator, |pager| self.render_taxonomy_term(taxonomy, term, Some(pager)))?
+            }
+            None => {
+                let output = self.render_taxonomy_term(taxonomy, term, None)?;
+                self.write_content(&components, "index.html", &output)?;
+            }
+        }
+
+        if taxonomy.kind.feed {
+            let mut context = TeraContext::new();
+            context.insert("taxonomy", &taxonomy.serialize());
+            context.insert("term", &term.serialize(&self.pages));
+            self.write_feeds(&term.pages, &components, &term.permalink, &context)
+                .with_context(|| format!("Failed to write the feeds of term `{}` of taxonomy `{}`", term.name, taxonomy.kind.name))?;
+        }
+        Ok(())
+    }
+
+    /// Writes a page along with everything listing it: its sections, taxonomy terms,
+    /// feeds and the search index
+    ///
+    /// Used after [`Site::reload_page`], the sitemap doesn't change then since the front
+    /// matter of the page is the same.
+    pub fn write_page_and_listings(&self, path: &Path) -> Result<()> {
+        self.write_page(&self.pages[path])?;
+        self.write_site_feeds()?;
+
+        for section in self.sections.values().filter(|section| section.pages.iter().any(|page| page == path)) {
+            self.write_section(section)?;
+        }
+
+        for taxonomy in &self.taxonomies {
+            let terms: Vec<_> =
+                taxonomy.items.iter().filter(|term| term.pages.iter().any(|page| page == path)).collect();
+            if terms.is_empty() {
+                continue;
+            }
+
+            self.write_taxonomy_list(taxonomy)?;
+            for term in terms {
+                self.write_taxonomy_term(taxonomy, term)?;
+            }
+        }
+
+        if self.config.build_search_index {
+            self.write_search_index()?;
+        }
         Ok(())
     }
 
     /// Build the output directory
     pub fn build_output_dir(&self) -> Result<()> {
         // Create output directory if it doesn't exist
-        if !self.output_path.exists() {
+        if self.memory_output.is_none() && !self.output_path.exists() {
             println!("Creating output directory: {}", self.output_path.display());
             std::fs::create_dir_all(&self.output_path)?;
         }
 
         // Copy static files
         self.copy_static_directories()?;
-        
-        // Create a simple index.html if it doesn't exist
-        // let index_path = self.output_path.join("index.html");
-        // if !index_path.exists() {
-        //     let content = format!(
-        //         r#"<!DOCTYPE html>
-        //                 <html>
-        //                 <head>
-        //                     <title>{}</title>
-        //                     <meta charset="utf-8">
-        //                 </head>
-        //                 <body>
-        //                     <h1>Welcome to {}</h1>
-        //                     <p>Your site was successfully built!</p>
-        //                 </body>
-        //                 </html>"#,
-        //         self.config.title.as_deref().unwrap_or("My Site"),
-        //         self.config.title.as_deref().unwrap_or("My Site")
-        //     );
-        //     std::fs::write(&index_path, content)?;
-        //     println!("Created default index.html");
-        // }
-        
+        self.compile_sass()?;
+
+        for page in self.pages.values() {
+            self.write_page(page)?;
+        }
+
+        self.write_site_feeds()?;
+        for section in self.sections.values() {
+            self.write_section(section)?;
+        }
+
+        for taxonomy in &self.taxonomies {
+            self.write_taxonomy_list(taxonomy)?;
+            for term in &taxonomy.items {
+                self.write_taxonomy_term(taxonomy, term)?;
+            }
+        }
+
+        self.write_sitemap()?;
+        self.write_special_pages()?;
+        if self.config.build_search_index {
+            self.write_search_index()?;
+        }
+
         Ok(())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// A site with a published post and a draft, both tagged
+    fn site_with_draft(include_drafts: bool) -> (tempfile::TempDir, Site) {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("config.toml"),
+            "base_url = \"https://example.com\"\n[[taxonomies]]\nname = \"tags\"",
+        )
+        .unwrap();
+        fs::create_dir_all(dir.path().join("content/blog")).unwrap();
+        fs::write(dir.path().join("content/blog/_index.md"), "+++\n+++\n").unwrap();
+        fs::write(
+            dir.path().join("content/blog/post.md"),
+            "+++\ntitle = \"Post\"\n[taxonomies]\ntags = [\"rust\"]\n+++\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("content/blog/draft.md"),
+            "+++\ntitle = \"Draft\"\ndraft = true\n[taxonomies]\ntags = [\"rust\", \"wip\"]\n+++\n",
+        )
+        .unwrap();
+
+        let mut site = Site::new(dir.path(), "config.toml").unwrap();
+        if include_drafts {
+            site.include_drafts();
+        }
+        site.write_to_memory(MemoryOutput::default());
+        site.load_files().unwrap();
+        site.build_output_dir().unwrap();
+        (dir, site)
+    }
+
+    #[test]
+    fn test_drafts_are_left_out_by_default() {
+        let (dir, site) = site_with_draft(false);
+        let draft = dir.path().join("content/blog/draft.md");
+
+        assert!(!site.pages.contains_key(&draft));
+        let blog = &site.sections[&dir.path().join("content/blog/_index.md")];
+        assert_eq!(blog.pages, vec![dir.path().join("content/blog/post.md")]);
+        let terms: Vec<_> = site.taxonomies[0].items.iter().map(|term| term.name.as_str()).collect();
+        assert_eq!(terms, vec!["rust"]);
+
+        let output = site.memory_output.as_ref().unwrap().read().unwrap();
+        assert!(output.contains_key("/blog/post/index.html"));
+        assert!(!output.contains_key("/blog/draft/index.html"));
+        assert!(!output.contains_key("/tags/wip/index.html"));
+    }
+
+    #[test]
+    fn test_drafts_are_included_when_asked_for() {
+        let (dir, site) = site_with_draft(true);
+
+        assert!(site.pages.contains_key(&dir.path().join("content/blog/draft.md")));
+        assert_eq!(site.sections[&dir.path().join("content/blog/_index.md")].pages.len(), 2);
+
+        let output = site.memory_output.as_ref().unwrap().read().unwrap();
+        assert!(output.contains_key("/blog/draft/index.html"));
+        assert!(output.contains_key("/tags/wip/index.html"));
+    }
+
+    #[test]
+    fn test_page_listings_are_written_with_the_page() {
+        let (dir, site) = site_with_draft(false);
+        let memory_output = site.memory_output.clone().unwrap();
+        memory_output.write().unwrap().clear();
+
+        site.write_page_and_listings(&dir.path().join("content/blog/post.md")).unwrap();
+
+        let mut written: Vec<_> = memory_output.read().unwrap().keys().cloned().collect();
+        written.sort();
+        assert_eq!(
+            written,
+            vec!["/blog/index.html", "/blog/post/index.html", "/tags/index.html", "/tags/rust/index.html"]
+        );
+    }
+
+    #[test]
+    fn test_publishing_a_full_build_replaces_the_memory_output() {
+        let (_dir, site) = site_with_draft(false);
+        let memory_output = site.memory_output.clone().unwrap();
+        let files = |paths: &[&str]| paths.iter().map(|path| (path.to_string(), Vec::new())).collect();
+
+        site.publish_output(files(&["/new/index.html"]), false).unwrap();
+        assert!(memory_output.read().unwrap().contains_key("/blog/post/index.html"));
+        assert!(memory_output.read().unwrap().contains_key("/new/index.html"));
+
+        site.publish_output(files(&["/index.html"]), true).unwrap();
+        let output = memory_output.read().unwrap();
+        assert_eq!(output.keys().collect::<Vec<_>>(), vec!["/index.html"]);
+    }
+}
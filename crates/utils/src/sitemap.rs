@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+use crate::page::Page;
+use crate::section::Section;
+use crate::taxonomies::Taxonomy;
+
+/// Most entries a single sitemap file may have, past it the sitemap is split
+/// and `sitemap.xml` becomes an index of the parts
+pub const MAX_SITEMAP_ENTRIES: usize = 30_000;
+
+/// A URL of the site listed in the sitemap
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct SitemapEntry {
+    pub permalink: String,
+    /// When the content was last modified, from the `updated` or `date` of pages
+    pub lastmod: Option<String>,
+}
+
+impl SitemapEntry {
+    fn new(permalink: &str, lastmod: Option<&str>) -> SitemapEntry {
+        SitemapEntry { permalink: permalink.to_string(), lastmod: lastmod.map(|date| w3c_datetime(date).to_string()) }
+    }
+}
+
+/// A front matter date as a W3C Datetime, which has no times without a timezone so
+/// those are cut down to the day
+fn w3c_datetime(date: &str) -> &str {
+    match date.get(10..) {
+        Some(time) if !time.is_empty() && !time.ends_with('Z') && !time.contains(['+', '-']) => &date[..10],
+        _ => date,
+    }
+}
+
+/// Every page, rendered section and taxonomy term of the site, sorted by permalink
+///
+/// Sections and terms have no date of their own, they were last modified with
+/// their most recent page.
+pub fn find_entries(
+    pages: &HashMap<PathBuf, Page>,
+    sections: &HashMap<PathBuf, Section>,
+    taxonomies: &[Taxonomy],
+) -> Vec<SitemapEntry> {
+    let latest = |paths: &[PathBuf]| paths.iter().filter_map(|path| pages[path].last_updated()).max();
+
+    let mut entries: Vec<SitemapEntry> = pages
+        .values()
+        .map(|page| SitemapEntry::new(&page.permalink, page.last_updated()))
+        .collect();
+
+    entries.extend(
+        sections
+            .values()
+            .filter(|section| section.meta.render)
+            .map(|section| SitemapEntry::new(&section.permalink, latest(&section.pages))),
+    );
+
+    for taxonomy in taxonomies {
+        entries.push(SitemapEntry::new(&taxonomy.permalink, None));
+        entries.extend(
+            taxonomy.items.iter().map(|term| SitemapEntry::new(&term.permalink, latest(&term.pages))),
+        );
+    }
+
+    entries.sort();
+    entries.dedup_by(|a, b| a.permalink == b.permalink);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::taxonomies::TaxonomyTerm;
+
+    fn page(file: &str, date: Option<&str>, updated: Option<&str>) -> (PathBuf, Page) {
+        let mut page = Page {
+            file: file.to_string(),
+            permalink: format!("https://example.com/{}/", file.trim_end_matches(".md")),
+            ..Page::default()
+        };
+        page.meta.date = date.map(str::to_string);
+        page.meta.updated = updated.map(str::to_string);
+        (PathBuf::from(file), page)
+    }
+
+    #[test]
+    fn test_find_entries() {
+        let pages: HashMap<PathBuf, Page> = [
+            page("a.md", Some("2020-01-01"), Some("2023-05-01")),
+            page("b.md", Some("2022-01-01"), None),
+            page("c.md", None, None),
+            page("d.md", Some("2024-03-01T10:00:00"), None),
+            page("e.md", Some("2024-03-01T10:00:00+02:00"), None),
+        ]
+        .into_iter()
+        .collect();
+
+        let mut blog = Section { permalink: "https://example.com/blog/".to_string(), ..Section::default() };
+        blog.pages = vec![PathBuf::from("b.md"), PathBuf::from("c.md")];
+        let mut hidden = Section { permalink: "https://example.com/hidden/".to_string(), ..Section::default() };
+        hidden.meta.render = false;
+        let sections: HashMap<PathBuf, Section> =
+            [(PathBuf::from("blog/_index.md"), blog), (PathBuf::from("hidden/_index.md"), hidden)]
+                .into_iter()
+                .collect();
+
+        let tags = Taxonomy {
+            permalink: "https://example.com/tags/".to_string(),
+            items: vec![TaxonomyTerm {
+                permalink: "https://example.com/tags/rust/".to_string(),
+                pages: vec![PathBuf::from("a.md"), PathBuf::from("b.md")],
+                ..TaxonomyTerm::default()
+            }],
+            ..Taxonomy::default()
+        };
+
+        let entries = find_entries(&pages, &sections, &[tags]);
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.permalink.trim_start_matches("https://example.com"), entry.lastmod.as_deref()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("/a/", Some("2023-05-01")),
+                ("/b/", Some("2022-01-01")),
+                ("/blog/", Some("2022-01-01")),
+                ("/c/", None),
+                ("/d/", Some("2024-03-01")),
+                ("/e/", Some("2024-03-01T10:00:00+02:00")),
+                ("/tags/", None),
+                ("/tags/rust/", Some("2023-05-01")),
+            ]
+        );
+    }
+}
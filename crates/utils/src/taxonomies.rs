@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use anyhow::{bail, Result};
+use libs::slug::slugify;
+use serde::{Deserialize, Serialize};
+
+use crate::page::{Page, SerializedPage};
+use crate::section::{sort_by_key, DEFAULT_PAGINATE_PATH};
+use crate::site::Config;
+
+/// A taxonomy declared with `[[taxonomies]]` in `config.toml`
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct TaxonomyConfig {
+    /// Name of the taxonomy, as used in the front matter of pages
+    pub name: String,
+
+    /// Number of pages per pager on term pages, no pagination when not set
+    pub paginate_by: Option<usize>,
+
+    /// Path segment before the pager index, e.g. `page` in `/tags/rust/page/2/`, `page` when empty
+    pub paginate_path: String,
+
+    /// Whether feeds are generated for every term of the taxonomy, see `feed_filenames`
+    pub feed: bool,
+}
+
+impl Default for TaxonomyConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            paginate_by: None,
+            paginate_path: DEFAULT_PAGINATE_PATH.to_string(),
+            feed: false,
+        }
+    }
+}
+
+/// A single term of a taxonomy, e.g. the `rust` tag
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TaxonomyTerm {
+    pub name: String,
+    pub slug: String,
+    /// URL path of the term, e.g. `/tags/rust/`
+    pub path: String,
+    pub permalink: String,
+    /// File paths of the pages having the term, most recent first
+    pub pages: Vec<PathBuf>,
+}
+
+/// Serialized version of a taxonomy term for template rendering
+#[derive(Debug, Serialize)]
+pub struct SerializedTaxonomyTerm<'a> {
+    name: &'a str,
+    slug: &'a str,
+    path: &'a str,
+    permalink: &'a str,
+    pages: Vec<SerializedPage<'a>>,
+    page_count: usize,
+}
+
+impl TaxonomyTerm {
+    pub fn serialize<'a>(&'a self, pages: &'a HashMap<PathBuf, Page>) -> SerializedTaxonomyTerm<'a> {
+        SerializedTaxonomyTerm {
+            name: &self.name,
+            slug: &self.slug,
+            path: &self.path,
+            permalink: &self.permalink,
+            pages: self.pages.iter().map(|path| pages[path].serialize()).collect(),
+            page_count: self.pages.len(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Taxonomy {
+    pub kind: TaxonomyConfig,
+    pub slug: String,
+    /// URL path of the taxonomy, e.g. `/tags/`
+    pub path: String,
+    pub permalink: String,
+    /// Terms of the taxonomy, sorted by name
+    pub items: Vec<TaxonomyTerm>,
+}
+
+/// Serialized version of a taxonomy for template rendering, without its terms
+#[derive(Debug, Serialize)]
+pub struct SerializedTaxonomy<'a> {
+    name: &'a str,
+    slug: &'a str,
+    path: &'a str,
+    permalink: &'a str,
+}
+
+impl Taxonomy {
+    pub fn serialize(&self) -> SerializedTaxonomy<'_> {
+        SerializedTaxonomy {
+            name: &self.kind.name,
+            slug: &self.slug,
+            path: &self.path,
+            permalink: &self.permalink,
+        }
+    }
+}
+
+/// Groups the pages of the site by the taxonomy terms found in their front matter
+///
+/// Terms are matched by slug, the first spelling found is the one displayed.
+pub fn find_taxonomies(config: &Config, pages: &HashMap<PathBuf, Page>) -> Result<Vec<Taxonomy>> {
+    let mut page_paths: Vec<&PathBuf> = pages.keys().collect();
+    page_paths.sort();
+
+    let mut taxonomies = Vec::new();
+    for kind in &config.taxonomies {
+        let slug = slugify(&kind.name);
+        let path = format!("/{}/", slug);
+        let mut items: Vec<TaxonomyTerm> = Vec::new();
+
+        for page_path in &page_paths {
+            let Some(terms) = pages[*page_path].meta.taxonomies.get(&kind.name) else {
+                continue;
+            };
+
+            for name in terms {
+                let term_slug = slugify(name);
+                let index = match items.iter().position(|term| term.slug == term_slug) {
+                    Some(index) => index,
+                    None => {
+                        let term_path = format!("{}{}/", path, term_slug);
+                        items.push(TaxonomyTerm {
+                            name: name.clone(),
+                            slug: term_slug,
+                            permalink: config.make_permalink(&term_path),
+                            path: term_path,
+                            pages: Vec::new(),
+                        });
+                        items.len() - 1
+                    }
+                };
+                items[index].pages.push((*page_path).clone());
+            }
+        }
+
+        for term in &mut items {
+            sort_by_key(&mut term.pages, true, |path| pages[path].meta.date.as_deref());
+        }
+        items.sort_by_key(|item| item.name.to_lowercase());
+
+        taxonomies.push(Taxonomy {
+            kind: kind.clone(),
+            permalink: config.make_permalink(&path),
+            slug,
+            path,
+            items,
+        });
+    }
+
+    // a typo in the front matter would otherwise silently drop the terms
+    for page_path in page_paths {
+        let page = &pages[page_path];
+        for name in page.meta.taxonomies.keys() {
+            if !config.taxonomies.iter().any(|kind| &kind.name == name) {
+                bail!(
+                    "Page {} uses the taxonomy `{}` which is not declared in `[[taxonomies]]` of the config",
+                    page.file,
+                    name
+                );
+            }
+        }
+    }
+
+    Ok(taxonomies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page_with_tags(file: &str, date: &str, tags: &[&str]) -> (PathBuf, Page) {
+        let mut page = Page { file: file.to_string(), ..Page::default() };
+        page.meta.date = Some(date.to_string());
+        page.meta.taxonomies.insert("tags".to_string(), tags.iter().map(|tag| tag.to_string()).collect());
+        (PathBuf::from(file), page)
+    }
+
+    #[test]
+    fn test_find_taxonomies_groups_terms_by_slug() {
+        let config = Config::parse("base_url = \"https://example.com\"\n[[taxonomies]]\nname = \"tags\"").unwrap();
+        let pages: HashMap<PathBuf, Page> = [
+            page_with_tags("a.md", "2020-01-01", &["Rust"]),
+            page_with_tags("b.md", "2022-01-01", &["rust", "Web Dev"]),
+        ]
+        .into_iter()
+        .collect();
+
+        let taxonomies = find_taxonomies(&config, &pages).unwrap();
+        assert_eq!(taxonomies.len(), 1);
+
+        let tags = &taxonomies[0];
+        assert_eq!(tags.permalink, "https://example.com/tags/");
+        assert_eq!(tags.items.len(), 2);
+        assert_eq!(tags.items[0].name, "Rust");
+        assert_eq!(tags.items[0].path, "/tags/rust/");
+        assert_eq!(tags.items[0].pages, vec![PathBuf::from("b.md"), PathBuf::from("a.md")]);
+        assert_eq!(tags.items[1].slug, "web-dev");
+    }
+
+    #[test]
+    fn test_undeclared_taxonomy_is_an_error() {
+        let config = Config::parse("base_url = \"https://example.com\"").unwrap();
+        let pages: HashMap<PathBuf, Page> = [page_with_tags("a.md", "2020-01-01", &["rust"])].into_iter().collect();
+
+        let err = find_taxonomies(&config, &pages).unwrap_err().to_string();
+        assert!(err.contains("a.md"));
+        assert!(err.contains("`tags`"));
+    }
+}
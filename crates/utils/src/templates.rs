@@ -0,0 +1,202 @@
+use std::error::Error as StdError;
+use std::path::Path;
+use errors::{ anyhow, Result };
+use libs::tera::{escape_html, ErrorKind, Tera};
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+/// `Failed to parse "path"`, the position of the error and what was expected there
+static SYNTAX_ERROR_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"Failed to parse "([^"]+)"\s*--> (\d+):(\d+)(?s:.*?)\n\s*= ([^\n]+)"#).unwrap()
+});
+
+/// Templates shipped with Genetics, used when the site doesn't provide its own
+///
+/// The HTML ones are fallbacks letting a new site build before it has any template.
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("page.html", include_str!("builtins/page.html")),
+    ("section.html", include_str!("builtins/section.html")),
+    ("index.html", include_str!("builtins/index.html")),
+    ("taxonomy_list.html", include_str!("builtins/taxonomy_list.html")),
+    ("taxonomy_single.html", include_str!("builtins/taxonomy_single.html")),
+    ("atom.xml", include_str!("builtins/atom.xml")),
+    ("rss.xml", include_str!("builtins/rss.xml")),
+    ("sitemap.xml", include_str!("builtins/sitemap.xml")),
+    ("split_sitemap_index.xml", include_str!("builtins/split_sitemap_index.xml")),
+    ("robots.txt", include_str!("builtins/robots.txt")),
+    ("404.html", include_str!("builtins/404.html")),
+];
+
+/// Reports a syntax error of a template as `path:line:column: expected ...`
+///
+/// Tera reports it after a generic message, in the sources of the error, and stops at the
+/// first one. Errors that aren't about the syntax are returned as they are.
+fn template_error(err: libs::tera::Error, templates_path: &Path) -> errors::Error {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message.push('\n');
+        message.push_str(&err.to_string());
+        source = err.source();
+    }
+
+    match SYNTAX_ERROR_REGEX.captures(&message) {
+        Some(captures) => anyhow!(
+            "Template syntax error:\n  {}:{}:{}: {}",
+            &captures[1],
+            &captures[2],
+            &captures[3],
+            captures[4].trim()
+        ),
+        None => anyhow!(err).context(format!("Failed to load templates from {}", templates_path.display())),
+    }
+}
+
+/// The directory the template an inheritance error is about comes from, the templates of
+/// the theme or else of the site
+fn failing_templates_path<'a>(
+    tera: &Tera,
+    err: &libs::tera::Error,
+    templates_path: &'a Path,
+    theme_templates_path: Option<&'a Path>,
+) -> &'a Path {
+    let name = match &err.kind {
+        ErrorKind::MissingParent { current, .. } => current,
+        ErrorKind::CircularExtend { tpl, .. } => tpl,
+        _ => return templates_path,
+    };
+    let template_path = tera.templates.get(name).and_then(|template| template.path.as_deref());
+
+    match (template_path, theme_templates_path) {
+        (Some(path), Some(theme_templates_path)) if Path::new(path).starts_with(theme_templates_path) => {
+            theme_templates_path
+        }
+        _ => templates_path,
+    }
+}
+
+/// Parses the templates of a directory, without checking what they extend exists
+fn parse_templates(templates_path: &Path) -> Result<Tera> {
+    if !templates_path.exists() {
+        return Ok(Tera::default());
+    }
+
+    let glob = format!("{}/**/*.{{*ml,txt}}", templates_path.display());
+    Tera::parse(&glob).map_err(|err| template_error(err, templates_path))
+}
+
+/// Loads every template found in the templates directory into a single Tera instance
+///
+/// A missing templates directory is not an error, the site simply has no templates.
+/// The templates of the theme, then the built-in ones, are added for any name the site
+/// doesn't define itself. Templates can `{% extends %}` and `{% include %}` any other
+/// one by its path in its directory, e.g. a site template can extend a theme one.
+pub fn load_tera(templates_path: &Path, theme_templates_path: Option<&Path>) -> Result<Tera> {
+    // `extend` checks what every template extends exists, the theme may be the culprit
+    let inheritance_error = |tera: &Tera, err: libs::tera::Error| {
+        let failing_path = failing_templates_path(tera, &err, templates_path, theme_templates_path);
+        template_error(err, failing_path)
+    };
+
+    let mut tera = parse_templates(templates_path)?;
+    if let Some(theme_templates_path) = theme_templates_path {
+        // `extend` never overwrites a template the site already defines
+        let theme = parse_templates(theme_templates_path)?;
+        tera.extend(&theme).map_err(|err| inheritance_error(&tera, err))?;
+    }
+
+    let mut builtins = Tera::default();
+    builtins.add_raw_templates(BUILTIN_TEMPLATES.to_vec())?;
+    tera.extend(&builtins).map_err(|err| inheritance_error(&tera, err))?;
+    tera.build_inheritance_chains().map_err(|err| inheritance_error(&tera, err))?;
+
+    Ok(tera)
+}
+
+/// A page redirecting to `url`, used when the same content is reachable from another URL
+pub fn render_redirect_template(url: &str) -> String {
+    let url = escape_html(url);
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <link rel="canonical" href="{url}">
+    <meta http-equiv="refresh" content="0; url={url}">
+    <title>Redirect</title>
+</head>
+<body>
+    <p><a href="{url}">Click here</a> to be redirected.</p>
+</body>
+</html>
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_site_templates_override_builtins() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("robots.txt"), "User-agent: *\nDisallow: /").unwrap();
+
+        let tera = load_tera(dir.path(), None).unwrap();
+        let context = libs::tera::Context::new();
+        assert_eq!(tera.render("robots.txt", &context).unwrap(), "User-agent: *\nDisallow: /");
+        assert!(tera.get_template_names().any(|name| name == "404.html"));
+    }
+
+    #[test]
+    fn test_templates_extend_and_include_each_other() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("partials")).unwrap();
+        std::fs::write(dir.path().join("base.html"), "<{% block body %}{% endblock %}>").unwrap();
+        std::fs::write(dir.path().join("partials/nav.html"), "nav").unwrap();
+        std::fs::write(
+            dir.path().join("page.html"),
+            "{% extends \"base.html\" %}{% block body %}{% include \"partials/nav.html\" %}{% endblock %}",
+        )
+        .unwrap();
+
+        let tera = load_tera(dir.path(), None).unwrap();
+        assert_eq!(tera.render("page.html", &libs::tera::Context::new()).unwrap(), "<nav>");
+    }
+
+    #[test]
+    fn test_site_templates_override_theme_ones() {
+        let site = tempfile::tempdir().unwrap();
+        let theme = tempfile::tempdir().unwrap();
+        std::fs::write(theme.path().join("base.html"), "theme {% block body %}{% endblock %}").unwrap();
+        std::fs::write(theme.path().join("page.html"), "{% extends \"base.html\" %}{% block body %}page{% endblock %}").unwrap();
+        std::fs::write(theme.path().join("section.html"), "theme section").unwrap();
+        std::fs::write(site.path().join("page.html"), "{% extends \"base.html\" %}{% block body %}mine{% endblock %}").unwrap();
+
+        let tera = load_tera(site.path(), Some(theme.path())).unwrap();
+        let context = libs::tera::Context::new();
+        assert_eq!(tera.render("page.html", &context).unwrap(), "theme mine");
+        assert_eq!(tera.render("section.html", &context).unwrap(), "theme section");
+    }
+
+    #[test]
+    fn test_syntax_errors_have_file_and_line() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("page.html"), "<p>\n{% if a == b %}\n{{ page.title \n").unwrap();
+
+        let err = load_tera(dir.path(), None).unwrap_err().to_string();
+        let expected = format!("Template syntax error:\n  {}:4:1: expected", dir.path().join("page.html").display());
+        assert!(err.starts_with(&expected), "{}", err);
+    }
+
+    #[test]
+    fn test_theme_errors_name_the_theme_directory() {
+        let site = tempfile::tempdir().unwrap();
+        let theme = tempfile::tempdir().unwrap();
+        std::fs::write(theme.path().join("page.html"), "{% extends \"missing.html\" %}").unwrap();
+
+        let err = format!("{:#}", load_tera(site.path(), Some(theme.path())).unwrap_err());
+        let expected = format!("Failed to load templates from {}", theme.path().display());
+        assert!(err.starts_with(&expected), "{}", err);
+    }
+}
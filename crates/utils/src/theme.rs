@@ -0,0 +1,63 @@
+use std::path::Path;
+
+use errors::{bail, Context, Result};
+use libs::toml::{self, Table, Value};
+use serde::Deserialize;
+
+use crate::fs::read_file;
+
+/// The `theme.toml` at the root of a theme
+#[derive(Debug, Default, Deserialize)]
+pub struct Theme {
+    /// Defaults of the `extra` section of the config of sites using the theme
+    #[serde(default)]
+    pub extra: Table,
+}
+
+impl Theme {
+    /// Reads the `theme.toml` of the theme at `theme_path`, a theme without one has no defaults
+    pub fn from_directory(theme_path: &Path) -> Result<Theme> {
+        if !theme_path.is_dir() {
+            bail!("Theme directory {} not found", theme_path.display());
+        }
+
+        let path = theme_path.join("theme.toml");
+        if !path.exists() {
+            return Ok(Theme::default());
+        }
+
+        let content = read_file(&path)?;
+        toml::from_str(&content).with_context(|| format!("Invalid theme config {}", path.display()))
+    }
+}
+
+/// Adds the keys of `defaults` missing from `table`, merging the tables both have
+pub fn merge_defaults(table: &mut Table, defaults: &Table) {
+    for (key, default) in defaults {
+        match (table.get_mut(key), default) {
+            (Some(Value::Table(table)), Value::Table(defaults)) => merge_defaults(table, defaults),
+            (Some(_), _) => {}
+            (None, _) => {
+                table.insert(key.clone(), default.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_site_extra_wins_over_theme_defaults() {
+        let mut extra: Table = toml::from_str("author = \"Site\"\n[menu]\nhome = \"/\"").unwrap();
+        let defaults: Table =
+            toml::from_str("author = \"Theme\"\nlogo = \"logo.svg\"\n[menu]\nhome = \"/index\"\nblog = \"/blog\"").unwrap();
+
+        merge_defaults(&mut extra, &defaults);
+
+        let expected: Table =
+            toml::from_str("author = \"Site\"\nlogo = \"logo.svg\"\n[menu]\nhome = \"/\"\nblog = \"/blog\"").unwrap();
+        assert_eq!(extra, expected);
+    }
+}
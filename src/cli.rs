@@ -25,6 +25,26 @@ pub enum Command {
         base_url: Option<String>, 
 
         output_dir: Option<PathBuf>,
+
+        /// Include drafts when building the site
+        #[clap(long)]
+        drafts: bool,
+    },
+
+
+    /// Check the site for errors without building it, e.g. in CI
+    Check {
+        /// Overrides the `base_url` of the config
+        #[clap(short = 'u', long)]
+        base_url: Option<String>,
+
+        /// Include drafts in the check
+        #[clap(long)]
+        drafts: bool,
+
+        /// Also check that external links can be reached, see `[link_checker]` in the config
+        #[clap(long)]
+        external: bool,
     },
 
 
@@ -37,11 +57,21 @@ pub enum Command {
         
         output_dir: Option<PathBuf>,
 
+        /// Overrides the `base_url` of the config, its path being where the site is mounted
+        #[clap(short = 'u', long)]
         base_url: Option<String>,
 
         /// Open site in the default browser
         #[clap(short = 'O', long)]
         open: bool,
+
+        /// Include drafts when serving the site
+        #[clap(long)]
+        drafts: bool,
+
+        /// Write the site to the output directory instead of serving it from memory
+        #[clap(long)]
+        write_to_disk: bool,
     }
 }
 
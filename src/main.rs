@@ -5,7 +5,8 @@ use errors::Result;
 use cli::{ Cli, Command };
 
 
-use crate::utils::{fs::{ build_output_dir, create_file, get_current_config_path }, net::{available_port_checker, get_available_port, serve_site}};
+use crate::utils::check::check_site;
+use crate::utils::{fs::{ build_output_dir, create_file, get_current_config_path, ServeOptions }, net::{available_port_checker, get_available_port, serve_site}};
 
 mod cli;
 mod utils;
@@ -23,7 +24,7 @@ fn read_line() -> Result<String, String> {
 }
 
 /// Ask a yes/no question
-#[allow(dead_code)]
+#[allow(dead_code, clippy::only_used_in_recursion)]
 fn ask_bool(question: &str, default: bool) -> Result<bool, String> {
     let _ = io::stdout().flush();
     let input = read_line()?;
@@ -73,11 +74,11 @@ fn create_new_project(name: &str, force: bool) -> Result<(), String> {
             println!("Current directory already exists");
         }
         else {
-            println!("Directory {} already exists", path.to_string_lossy().to_string());
+            println!("Directory {} already exists", path.to_string_lossy());
         }
     }
 
-    println!("Creating project {}", path.to_string_lossy().to_string());
+    println!("Creating project {}", path.to_string_lossy());
     println!("Please enter some information about your project");
 
     let base_url = ask_url("> Enter your website's base URL", "https://xample.com")?;
@@ -105,9 +106,9 @@ fn populate_project(path: &Path, config: &str) -> Result<()>{
     }
 
     create_file(&path.join("config.toml"), config)?;
-    create_dir(&path.join("content"))?;
-    create_dir(&path.join("static"))?;
-    create_dir(&path.join("templates"))?;
+    create_dir(path.join("content"))?;
+    create_dir(path.join("static"))?;
+    create_dir(path.join("templates"))?;
 
     Ok(())
 }
@@ -126,15 +127,29 @@ fn main() {
             }
         }
 
-        Command::Build {base_url, output_dir } => {
+        Command::Build {base_url, output_dir, drafts } => {
             println!("\x1B[1;34m   \x1B[0m Building starting...");
             let start = Instant::now(); 
             let (root_dir, config_file) = get_current_config_path(&cli.root, &cli.config);
 
-            match build_output_dir(&root_dir, &config_file, base_url.as_deref(), output_dir.as_deref(), false) {
+            match build_output_dir(&root_dir, &config_file, base_url.as_deref(), output_dir.as_deref(), false, drafts) {
                 Ok(()) => println!("\x1B[1;32m   \x1B[0m Built successfully in {:?}", start.elapsed()),
                 Err(e) => {
-                    println!("Unable to build output directory: {}", &e);
+                    println!("Unable to build output directory: {:#}", &e);
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        Command::Check { base_url, drafts, external } => {
+            println!("\x1B[1;34m   \x1B[0m Checking site...");
+            let start = Instant::now();
+            let (root_dir, config_file) = get_current_config_path(&cli.root, &cli.config);
+
+            match check_site(&root_dir, &config_file, base_url.as_deref(), drafts, external) {
+                Ok(()) => println!("\x1B[1;32m   \x1B[0m Site checked successfully in {:?}", start.elapsed()),
+                Err(e) => {
+                    println!("\n{:#}", &e);
                     std::process::exit(1);
                 }
             }
@@ -146,6 +161,8 @@ fn main() {
             output_dir, 
             base_url,
             open,
+            drafts,
+            write_to_disk,
             // no_port_append,
         } => {
             //when port is not 1111, check if it is available
@@ -162,18 +179,19 @@ fn main() {
 
             let (root_dir, config_file) = get_current_config_path(&current_dir, &cli.config); 
             println!("\x1B[1;34m   \x1B[0m Serving starting..."); 
-            if let Err(err) = serve_site(
-                &root_dir,
+            let options = ServeOptions {
                 interface,
                 port,
-                output_dir.as_deref(),
-                false,
-                base_url.as_deref(),
-                &config_file,
+                output_dir: output_dir.as_deref(),
+                force: false,
+                base_url: base_url.as_deref(),
+                no_port_append: false,
+                include_drafts: drafts,
                 open,
-                false,
-            ) {
-                println!("Unable to serve site: {}", &err);
+                write_to_disk,
+            };
+            if let Err(err) = serve_site(&root_dir, &config_file, options) {
+                println!("Unable to serve site: {:#}", &err);
                 std::process::exit(1);
             }
         }
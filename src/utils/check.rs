@@ -0,0 +1,72 @@
+use std::collections::HashSet;
+use std::path::Path;
+use errors::{bail, Result};
+
+use utils::site::{MemoryOutput, Site};
+use markdown::render::check_markdown;
+
+
+/// Loads and renders the whole site like a build would, without writing anything
+///
+/// Config, front matter and template syntax errors stop the check right away since
+/// nothing can be checked past them, other errors are gathered and listed together.
+/// External links are only checked when `external` is set as it needs the network.
+pub fn check_site(
+    root_dir: &Path,
+    config_file: &Path,
+    base_url: Option<&str>,
+    include_drafts: bool,
+    external: bool,
+) -> Result<()> {
+    let mut site = Site::new(root_dir, config_file)?;
+    if include_drafts {
+        site.include_drafts();
+    }
+    if let Some(base_url) = base_url {
+        site.set_base_url(base_url.to_string());
+    }
+    site.write_to_memory(MemoryOutput::default());
+    site.load_files()?;
+
+    let mut errors = check_markdown(&mut site);
+
+    // templates are only rendered once the markdown is fine, to report the root cause
+    if errors.is_empty() {
+        if let Err(err) = site.build_output_dir() {
+            errors.push(err);
+        }
+    }
+
+    if external {
+        if let Err(err) = site.check_external_links() {
+            errors.push(err);
+        }
+    }
+
+    let internal_links: usize = site.pages.values().map(|page| page.internal_links.len()).sum::<usize>()
+        + site.sections.values().map(|section| section.internal_links.len()).sum::<usize>();
+    println!(
+        "Checked {} pages, {} sections and {} internal links",
+        site.pages.len(),
+        site.sections.len(),
+        internal_links,
+    );
+    if external {
+        let external_links: HashSet<&String> = site
+            .pages
+            .values()
+            .flat_map(|page| &page.external_links)
+            .chain(site.sections.values().flat_map(|section| &section.external_links))
+            .collect();
+        println!("Checked {} external links", external_links.len());
+    }
+
+    if errors.is_empty() {
+        return Ok(());
+    }
+
+    for err in &errors {
+        println!("\nError: {:#}", err);
+    }
+    bail!("Found {} error(s) while checking the site", errors.len())
+}
@@ -4,11 +4,17 @@ use ws::Sender;
 use std::net::{IpAddr, SocketAddr};
 use std::path::{ Path, PathBuf };
 use std::fs::{ create_dir_all, File };
+use std::sync::Mutex;
 use errors::{Context, Result, Error};
 use walkdir::WalkDir;
+use libs::serde_json;
+use libs::url::Url;
+use once_cell::sync::Lazy;
+use regex::Regex;
 
 
-use utils::site::Site;
+use utils::site::{MemoryOutput, Site};
+use markdown::render::render_markdown;
 
 
 /// Get the current config path
@@ -84,6 +90,8 @@ pub fn copy_file(src: &Path, dest: &Path, base_path: &Path)-> Result<()> {
     let target_path = dest.join(relative_path);
 
     create_parent(&target_path)?;
+    std::fs::copy(src, &target_path)
+        .with_context(|| format!("Failed to copy {} to {}", src.display(), target_path.display()))?;
 
     Ok(())
 }
@@ -105,7 +113,7 @@ pub fn copy_directory(src: &Path, dest: &Path) -> Result<()>{
           
         }
         else {
-            copy_file(entry.path(), &target_path, src)?;
+            copy_file(entry.path(), dest, src)?;
         }
     }
 
@@ -114,32 +122,74 @@ pub fn copy_directory(src: &Path, dest: &Path) -> Result<()>{
 
 
 
+/// The base URL of the site while serving it
+///
+/// Without an override, this is the address of the server followed by the path of the
+/// configured base URL, so a site deployed under `/docs/` is served under `/docs/` too.
+/// The port is added to an override lacking one unless `no_port_append` is set.
+pub fn serve_base_url(config_base_url: &str, base_url: Option<&str>, address: SocketAddr, no_port_append: bool) -> String {
+    let base_url = match base_url {
+        Some(base_url) if base_url.starts_with("http://") || base_url.starts_with("https://") => base_url.to_string(),
+        Some(base_url) => format!("http://{}", base_url),
+        None => {
+            let path = Url::parse(config_base_url)
+                .map(|url| url.path().to_string())
+                .unwrap_or_default();
+            return format!("http://{}{}", address, path).trim_end_matches('/').to_string();
+        }
+    };
+
+    match Url::parse(&base_url) {
+        Ok(mut url) => {
+            if !no_port_append && url.port().is_none() {
+                let _ = url.set_port(Some(address.port()));
+            }
+            url.to_string().trim_end_matches('/').to_string()
+        }
+        Err(_) => base_url.trim_end_matches('/').to_string(),
+    }
+}
+
+/// How the dev server builds and serves the site, from the flags of `serve`
+#[derive(Debug, Clone, Copy)]
+pub struct ServeOptions<'a> {
+    pub interface: IpAddr,
+    pub port: u16,
+    pub output_dir: Option<&'a Path>,
+    /// Overwrite the output directory when it already exists
+    pub force: bool,
+    pub base_url: Option<&'a str>,
+    /// Keep the port out of the base URL, e.g. behind a reverse proxy
+    pub no_port_append: bool,
+    pub include_drafts: bool,
+    /// Open the site in the default browser once it's served
+    pub open: bool,
+    /// Write the site to the output directory instead of serving it from memory
+    pub write_to_disk: bool,
+}
+
 pub fn generate_site(
     root_dir: &Path,
-    interface: IpAddr,
-    interface_port: u16,
-    output_dir: Option<&Path>,
-    force: bool,
-    base_url: Option<&str>,
     config_file: &Path,
-    mut no_port_append: bool,
+    options: &ServeOptions,
+    memory_output: Option<&MemoryOutput>,
 ) -> Result<(Site, SocketAddr, String)> {
 
     let mut site = Site::new(root_dir, config_file)?;
-    let address = SocketAddr::new(interface, interface_port);
+    if options.include_drafts {
+        site.include_drafts();
+    }
+    if let Some(memory_output) = memory_output {
+        site.write_to_memory(memory_output.clone());
+    }
+    let address = SocketAddr::new(options.interface, options.port);
 
     //when no base url is provided, use the interface address
-    let base_url = base_url.map_or_else(
-        || {
-            no_port_append = true; 
-            address.to_string()
-        },
-        |xm | xm.to_string(),
-    );
+    let base_url = serve_base_url(&site.config.base_url, options.base_url, address, options.no_port_append);
+    site.set_base_url(base_url.clone());
 
-
-    if let Some(output_dir) = output_dir {
-        if !force && output_dir.exists() {
+    if let Some(output_dir) = options.output_dir {
+        if !options.force && memory_output.is_none() && output_dir.exists() {
             return Err(Error::msg(format!(
                 "Directory '{}' already exists. Use --force to overwrite.",
                 output_dir.display(),
@@ -149,6 +199,7 @@ pub fn generate_site(
     }
 
     site.load_files()?; 
+    render_markdown(&mut site)?;
 
     site.build_output_dir()?;
 
@@ -160,9 +211,19 @@ pub fn generate_site(
 ///
 /// If `output_dir` is given and exists, it will be removed unless `force` is `false`.
 /// If `base_url` is given, it will be used to set the base URL of the site.
-/// TODO
-pub fn build_output_dir(root_dir: &Path, config_file: &Path, base_url: Option<&str>, output_dir: Option<&Path>, force: bool) -> Result<()>{
+/// Drafts are left out unless `include_drafts` is `true`.
+pub fn build_output_dir(
+    root_dir: &Path,
+    config_file: &Path,
+    base_url: Option<&str>,
+    output_dir: Option<&Path>,
+    force: bool,
+    include_drafts: bool,
+) -> Result<()>{
     let mut site = Site::new(root_dir, config_file)?;
+    if include_drafts {
+        site.include_drafts();
+    }
     if let Some(output_dir) = output_dir {
         if !force && output_dir.exists() {
             return Err(Error::msg(format!("Output directory {} already exists", output_dir.display())));
@@ -177,6 +238,7 @@ pub fn build_output_dir(root_dir: &Path, config_file: &Path, base_url: Option<&s
         site.set_base_url(b.to_string());
     }
     site.load_files()?;
+    render_markdown(&mut site)?;
     
     // Build the output directory and return the result
     site.build_output_dir()?;
@@ -216,8 +278,54 @@ pub fn build_output_dir(root_dir: &Path, config_file: &Path, base_url: Option<&s
 //     }
 // }
 
+/// Message of the last failed rebuild, sent to browsers connecting while the site is broken
+static SERVE_ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+/// `path/to/file.md:12`, with the line being optional
+static ERROR_FILE_REGEX: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"([^\s:'"`]+\.(?:md|html|xml|txt|toml))(?::(\d+))?"#).unwrap()
+});
+
+/// `line 12` in TOML errors, `--> 12:4` in template ones
+static ERROR_LINE_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:line |--> )(\d+)").unwrap());
+
+/// Finds the file and line an error is about, for the errors mentioning them
+pub fn error_location(message: &str) -> (Option<String>, Option<usize>) {
+    let captures = match ERROR_FILE_REGEX.captures(message) {
+        Some(captures) => captures,
+        None => return (None, None),
+    };
+
+    let line = captures
+        .get(2)
+        .or_else(|| ERROR_LINE_REGEX.captures(message).and_then(|c| c.get(1)))
+        .and_then(|line| line.as_str().parse().ok());
+    (Some(captures[1].to_string()), line)
+}
+
+/// The livereload message making the browsers show a build error
+fn error_message(err: &Error) -> String {
+    let message = format!("{:#}", err);
+    let (file, line) = error_location(&message);
+    serde_json::json!({
+        "command": "error",
+        "message": message,
+        "file": file,
+        "line": line,
+    })
+    .to_string()
+}
+
+/// The message of the last failed rebuild, if the site hasn't been built successfully since
+pub fn serve_error() -> Option<String> {
+    SERVE_ERROR.lock().unwrap().clone()
+}
+
 /// Builds the output directory for the site and sends a reload message to the broadcaster
 ///
+/// When the build failed, the error is sent instead for the browsers to show it, and they
+/// keep showing it until the next successful build reloads them.
+///
 /// # Arguments
 ///
 /// * `broadcaster` - A reference to the broadcaster
@@ -226,22 +334,59 @@ pub fn build_output_dir(root_dir: &Path, config_file: &Path, base_url: Option<&s
 pub fn build_output_dir_with_broadcaster(broadcaster: &Sender, res: Result<()>, reload_path: &str) {
     match res {
         Ok(_)  => {
-            broadcaster.send(format!(
+            *SERVE_ERROR.lock().unwrap() = None;
+            let sent = broadcaster.send(format!(
                 r#"
                 {{
                     "command": "reload",
-                    "path": {},
+                    "path": "{}",
                     "originalPath": "",
                     "liveCSS": true,
                     "liveImg": true,
                     "protocol": ["http://livereload.com/protocols/official-7"]
                 }}"#,
-                format!(r#""{}""#, reload_path)
-            ))
-            .unwrap();
+                reload_path
+            ));
+            if let Err(err) = sent {
+                println!("Unable to notify the browsers: {}", err);
+            }
         }
         Err(e) => {
-            println!("Error while building the site: {}", e);
+            println!("Error while building the site: {:#}", e);
+            let message = error_message(&e);
+            *SERVE_ERROR.lock().unwrap() = Some(message.clone());
+            if let Err(err) = broadcaster.send(message) {
+                println!("Unable to notify the browsers: {}", err);
+            }
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serve_base_url() {
+        let address: SocketAddr = "127.0.0.1:1111".parse().unwrap();
+
+        assert_eq!(serve_base_url("https://example.com", None, address, false), "http://127.0.0.1:1111");
+        assert_eq!(serve_base_url("https://example.com/docs/", None, address, false), "http://127.0.0.1:1111/docs");
+        assert_eq!(serve_base_url("https://example.com", Some("localhost/docs"), address, false), "http://localhost:1111/docs");
+        assert_eq!(serve_base_url("https://example.com", Some("https://preview.dev/"), address, true), "https://preview.dev");
+    }
+
+    #[test]
+    fn test_error_location() {
+        assert_eq!(
+            error_location("Found 1 broken internal link(s):\n  ./content/about.md:8: anchor `#x` doesn't exist"),
+            (Some("./content/about.md".to_string()), Some(8))
+        );
+        assert_eq!(
+            error_location("Invalid front matter in ./content/post.md: TOML parse error at line 2, column 7"),
+            (Some("./content/post.md".to_string()), Some(2))
+        );
+        assert_eq!(error_location("Failed to render 'page.html'"), (Some("page.html".to_string()), None));
+        assert_eq!(error_location("Something went wrong"), (None, None));
+    }
 }
\ No newline at end of file
@@ -1,3 +1,4 @@
 
+pub mod check;
 pub mod fs;
-pub mod net;
\ No newline at end of file
+pub mod net;
@@ -1,17 +1,32 @@
 use std::{net::{IpAddr, TcpListener}, path::{Path, PathBuf}, time::Duration};
-// use std::time::Instant;
+use std::time::Instant;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 use errors::{ Context, Result, anyhow};
 use std::thread;
 use ws::{ Message, Sender, WebSocket }; 
-use hyper::{ service::{make_service_fn, service_fn}, Body, Response, Server, StatusCode};
-use notify_debouncer_full::{new_debouncer, notify::RecursiveMode};
+use hyper::{ service::{make_service_fn, service_fn}, Server};
+use notify_debouncer_full::{new_debouncer, notify::{EventKind, RecursiveMode}, DebouncedEvent};
 use std::sync::mpsc::channel;
 use ctrlc;
 
-use crate::utils::{fs::{build_output_dir_with_broadcaster, generate_site}};
+use crate::utils::{fs::{build_output_dir_with_broadcaster, generate_site, serve_error, ServeOptions}};
+use markdown::render::{render_markdown, render_page_markdown};
+use utils::site::{MemoryOutput, Site};
 use crate::utils::{fs::create_directory}; 
+use utils::net::{handle_request, DevServer, DEFAULT_LIVE_RELOAD_PORT};
 
 
+/// What a changed file is to the site, which decides how much has to be rebuilt
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+    Content,
+    Templates,
+    StaticFiles,
+    Sass,
+    Config,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum WatchStatus {
     Required, 
@@ -40,34 +55,14 @@ pub enum WatchStatus {
 // }
 
 
-pub fn serve_site(
-    root_dir: &Path,
-    interface: IpAddr,
-    interface_port: u16,
-    output_dir: Option<&Path>,
-    force: bool,
-    base_url: Option<&str>,
-    config_file: &Path,
-    open: bool,
-    no_port_append: bool,
-) ->  Result<()> {
+pub fn serve_site(root_dir: &Path, config_file: &Path, options: ServeOptions) -> Result<()> {
+    // the site is kept in memory unless asked otherwise, not to clobber a production build
+    let memory_output = (!options.write_to_disk).then(MemoryOutput::default);
+
     //let start = Instant::now();
 
-    let (site, address, constructed_base_url) = generate_site(
-        root_dir,
-        interface,
-        interface_port,
-        output_dir,
-        force,
-        base_url,
-        config_file,
-        no_port_append,
-    )?;
-
-    // let base_path = match constructed_base_url.splitn(4, "/").nth(3) {
-    //     Some(xm) => format!("/{}", xm), 
-    //     None => "/".to_string(),
-    // };
+    let (mut site, address, constructed_base_url) = generate_site(root_dir, config_file, &options, memory_output.as_ref())?;
+
 
     
     if(TcpListener::bind(address)).is_err() {
@@ -77,16 +72,20 @@ pub fn serve_site(
     //let config_buf = PathBuf::from(config_file); 
     let root_dir_str = root_dir.to_str().expect("Invalid root directory");
 
-    let watch_vector = vec![
+    // only the theme in use is watched, not every theme of the site
+    let theme_dir = site.config.theme.as_ref().map(|theme| format!("themes/{}", theme));
+    let mut watch_vector = vec![
         (root_dir_str, WatchStatus::Required, RecursiveMode::NonRecursive),
-        ("content", WatchStatus::Required, RecursiveMode::NonRecursive),
+        ("content", WatchStatus::Required, RecursiveMode::Recursive),
         ("static", WatchStatus::Optional, RecursiveMode::Recursive),
         ("templates", WatchStatus::Optional, RecursiveMode::Recursive),
-       // ("themes", WatchStatus::Conditional(site.config.themes.is_some()), RecursiveMode::Recursive),
+        ("sass", WatchStatus::Optional, RecursiveMode::Recursive),
     ];
+    if let Some(theme_dir) = &theme_dir {
+        watch_vector.push((theme_dir.as_str(), WatchStatus::Optional, RecursiveMode::Recursive));
+    }
 
-    //let (tx, rx) = channel();
-    let (tx, _) = channel();
+    let (tx, rx) = channel();
     let mut debouncer = new_debouncer(Duration::from_secs(1), None, tx).unwrap();
 
 
@@ -101,7 +100,7 @@ pub fn serve_site(
 
         if watch_state {
             debouncer.watch(
-                &root_dir.join(entry),
+                root_dir.join(entry),
                 recursive_mode,
             )
             .with_context(|| format!("Unable to watch directory {}", entry))?;
@@ -112,12 +111,17 @@ pub fn serve_site(
 
     //watch the directories 
     // websocket 
-    const DEFAULT_WS_PORT: u16 = 8080;
-    let ws_port = site.config.live_reload.unwrap_or(DEFAULT_WS_PORT);
-    let ws_address = format!("{}:{}", interface, ws_port);
+    let ws_port = site.config.live_reload.unwrap_or(DEFAULT_LIVE_RELOAD_PORT);
+    let ws_address = format!("{}:{}", options.interface, ws_port);
     let output_path = site.output_path.clone();
 
-   // let static_root_path = std::fs::canonicalize(&output_path).unwrap(); //the output directory can be changed
+    let base_path = Arc::new(RwLock::new(site.config.base_path()));
+    let dev_server = DevServer {
+        output_path: output_path.clone(),
+        memory_output: memory_output.clone(),
+        live_reload_port: ws_port,
+        base_path: base_path.clone(),
+    };
 
     let broadcaster = {
         thread::spawn(move || {
@@ -128,16 +132,11 @@ pub fn serve_site(
 
             rt.block_on(async {
                 let servelet = make_service_fn(move |_| {
-                   // let static_root = static_root_path.clone();
-                   // let base_path = base_path.clone(); 
+                    let dev_server = dev_server.clone();
 
                     async {
-                        Ok::<_, hyper::Error>(service_fn(move |_| async {
-                            let response = Response::builder()
-                                .status(StatusCode::OK)
-                                .body(Body::empty())
-                                .unwrap();
-                            Ok::<_, hyper::Error>(response)
+                        Ok::<_, hyper::Error>(service_fn(move |req| {
+                            handle_request(req, dev_server.clone())
                         }))
                     }
                 });
@@ -147,7 +146,7 @@ pub fn serve_site(
 
                 println!("Listening on {}, {}", constructed_base_url, address);
 
-                if open {
+                if options.open {
                     if let Err(err) = open::that(&constructed_base_url) {
                         println!("Unable to open URL: {}", err);
                     }
@@ -160,9 +159,11 @@ pub fn serve_site(
 
 
         let ws_server = WebSocket::new(|output: Sender| {
-            move |msg: Message| {
+            // the handler has to return `ws::Result`, whose error we don't control
+            #[allow(clippy::result_large_err)]
+            let handler = move |msg: Message| {
                 if msg.into_text().unwrap().contains("hello") {
-                    return output.send(Message::text(
+                    output.send(Message::text(
                         r#"
                         {
                             "command": "hello",
@@ -170,11 +171,17 @@ pub fn serve_site(
                             "serverName": "Genetics"
                         }
                     "#
-                    ))
+                    ))?;
+
+                    // pages opened while the site is broken show the error right away
+                    if let Some(error) = serve_error() {
+                        return output.send(Message::text(error));
+                    }
                 }
 
                 Ok(())
-            }
+            };
+            handler
         })
         .unwrap();
 
@@ -206,7 +213,9 @@ pub fn serve_site(
         .join(",");
 
     println!("\nWatching directories: {}", watch_list);
-    create_directory(&output_path)?;
+    if options.write_to_disk {
+        create_directory(&output_path)?;
+    }
 
     // let watch_list = watchers
     //     .iter()
@@ -224,45 +233,181 @@ pub fn serve_site(
     })
     .expect("Unable to set Ctrl+C handler");
 
-    // let templates = |site: &mut Site| {
-    //     build_output_dir_with_broadcaster(
-    //         &broadcaster, 
-    //         site.reload_templates, 
-    //         &site.templates_path.to_string_lossy()
-    //     );
-    // };
-
-    
-    let create_site = move || -> Result<()> {
-        let _ = match generate_site(
-            root_dir, 
-            interface, 
-            interface_port, 
-            output_dir, 
-            force, 
-            base_url, 
-            config_file, 
-            no_port_append
-    ) {
-            Ok((_, _, _)) => {
-                //clean up serve error if there's
-                // perform rebuilding of site
-                build_output_dir_with_broadcaster(
-                    &broadcaster, 
-                    Ok(()), 
-                    "/x.js"
-                );
-                Ok(())
-            },
-            Err(err) => {
-                println!("Unable to serve site: {}", err);
-                Err(err)
-            },
+    for result in rx {
+        let events = match result {
+            Ok(events) => events,
+            Err(errors) => {
+                for err in errors {
+                    println!("Watch error: {}", err);
+                }
+                continue;
+            }
         };
-        Ok(())
+
+        for (kind, path) in changes(&events, root_dir, &config_path) {
+            let start = Instant::now();
+            println!("Change detected in {}", path.display());
+
+            let result = match kind {
+                ChangeKind::Config => reload_site(&mut site, root_dir, config_file, &options).map(|()| {
+                    *base_path.write().unwrap() = site.config.base_path();
+                }),
+                // copying or removing a single file can't leave the output half built
+                ChangeKind::StaticFiles => site.copy_static_file(&path),
+                _ => staged_rebuild(&mut site, kind, &path),
+            };
+
+            let reload_path = match kind {
+                ChangeKind::StaticFiles => site.static_url(&path).unwrap_or_default(),
+                _ => String::new(),
+            };
+
+            if result.is_ok() {
+                println!("Done in {}ms", start.elapsed().as_millis());
+            }
+            build_output_dir_with_broadcaster(&broadcaster, result, &reload_path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Takes the files built into a staging output, leaving it empty
+fn take_output(staging: &MemoryOutput) -> HashMap<String, Vec<u8>> {
+    std::mem::take(&mut *staging.write().unwrap())
+}
+
+/// Builds the site again after a config change, keeping the current site and its output
+/// when the build fails
+fn reload_site(site: &mut Site, root_dir: &Path, config_file: &Path, options: &ServeOptions) -> Result<()> {
+    let staging = MemoryOutput::default();
+    let options = ServeOptions { force: true, ..*options };
+    let (mut new_site, _, _) = generate_site(root_dir, config_file, &options, Some(&staging))?;
+
+    new_site.memory_output = site.memory_output.clone();
+    new_site.publish_output(take_output(&staging), true)?;
+    *site = new_site;
+    Ok(())
+}
+
+/// Rebuilds the site into a staging output, see [`rebuild`]
+///
+/// The staged files get published only once the whole rebuild succeeded, a failed one
+/// leaving the last good build served.
+fn staged_rebuild(site: &mut Site, kind: ChangeKind, path: &Path) -> Result<()> {
+    let staging = MemoryOutput::default();
+    let live_output = site.memory_output.replace(staging.clone());
+    let full_build = rebuild(site, kind, path);
+
+    site.memory_output = live_output;
+    site.publish_output(take_output(&staging), full_build?)
+}
+
+/// Does the smallest rebuild of the site needed after a change to `path`, returning
+/// whether the whole site was built
+///
+/// Config and static files changes aren't handled here, the former need a brand new site
+/// and the latter are copied as they are.
+fn rebuild(site: &mut Site, kind: ChangeKind, path: &Path) -> Result<bool> {
+    match kind {
+        ChangeKind::Sass => site.compile_sass().map(|()| false),
+        ChangeKind::Templates => {
+            site.reload_templates()?;
+            site.build_output_dir()?;
+            Ok(true)
+        }
+        ChangeKind::Content => {
+            if site.reload_page(path)? {
+                render_page_markdown(site, path)?;
+                site.write_page_and_listings(path)?;
+                return Ok(false);
+            }
+
+            site.load_files()?;
+            render_markdown(site)?;
+            site.build_output_dir()?;
+            Ok(true)
+        }
+        ChangeKind::Config | ChangeKind::StaticFiles => unreachable!("{:?} changes aren't rebuilt", kind),
+    }
+}
+
+/// Editors write swap and backup files next to the files being edited
+fn is_temp_file(path: &Path) -> bool {
+    let file_name = match path.file_name() {
+        Some(name) => name.to_string_lossy(),
+        None => return true,
     };
 
-    create_site() //run the site
+    file_name.starts_with('.')
+        || file_name.starts_with('#')
+        || file_name.ends_with('~')
+        || matches!(path.extension().and_then(|x| x.to_str()), Some("swp" | "swx" | "tmp"))
+}
+
+/// Tells what a changed path is to the site, `None` for paths the site doesn't care about
+pub fn detect_change_kind(root_dir: &Path, path: &Path, config_path: &Path) -> Option<ChangeKind> {
+    if is_temp_file(path) {
+        return None;
+    }
+
+    if path == config_path {
+        return Some(ChangeKind::Config);
+    }
+
+    let relative_path = path.strip_prefix(root_dir).ok()?;
+    let mut components = relative_path.components().map(|component| component.as_os_str().to_str());
+    let mut directory = components.next()??;
+    if directory == "themes" {
+        // `themes/<name>/templates/page.html` is a template, `themes/<name>/theme.toml` the config
+        components.next()??;
+        directory = components.next()??;
+        if directory == "theme.toml" {
+            return Some(ChangeKind::Config);
+        }
+    }
+
+    match directory {
+        "content" => Some(ChangeKind::Content),
+        "templates" => Some(ChangeKind::Templates),
+        "static" => Some(ChangeKind::StaticFiles),
+        "sass" => Some(ChangeKind::Sass),
+        _ => None,
+    }
+}
+
+/// The changed paths of a batch of events, once each, with what they are to the site
+///
+/// A config change makes everything else in the batch irrelevant. A template change
+/// rebuilds every page and compiles the sass, but with the content already loaded, so it
+/// comes first and the content changes of the batch are reloaded on top of it.
+fn changes(events: &[DebouncedEvent], root_dir: &Path, config_path: &Path) -> Vec<(ChangeKind, PathBuf)> {
+    let mut changes: Vec<(ChangeKind, PathBuf)> = Vec::new();
+    for event in events {
+        if matches!(event.kind, EventKind::Access(_) | EventKind::Other) {
+            continue;
+        }
+
+        for path in &event.paths {
+            let kind = match detect_change_kind(root_dir, path, config_path) {
+                Some(kind) => kind,
+                None => continue,
+            };
+            if !changes.iter().any(|(k, p)| *k == kind && p == path) {
+                changes.push((kind, path.clone()));
+            }
+        }
+    }
+
+    if let Some(config) = changes.iter().find(|(kind, _)| *kind == ChangeKind::Config) {
+        return vec![config.clone()];
+    }
+    if let Some(templates) = changes.iter().find(|(kind, _)| *kind == ChangeKind::Templates).cloned() {
+        changes.retain(|(kind, _)| matches!(kind, ChangeKind::Content | ChangeKind::StaticFiles));
+        changes.insert(0, templates);
+    }
+
+    changes
 }
 
 
@@ -280,5 +425,54 @@ pub fn available_port_checker(interface:IpAddr, port: u16) -> bool {
 
 #[cfg(test)]
 mod test {
-    
+    use notify_debouncer_full::notify::{event::ModifyKind, Event};
+
+    use super::*;
+
+    #[test]
+    fn test_detect_change_kind() {
+        let root = Path::new("/site");
+        let config = Path::new("/site/config.toml");
+        let kind = |path: &str| detect_change_kind(root, Path::new(path), config);
+
+        assert_eq!(kind("/site/config.toml"), Some(ChangeKind::Config));
+        assert_eq!(kind("/site/content/blog/post.md"), Some(ChangeKind::Content));
+        assert_eq!(kind("/site/templates/page.html"), Some(ChangeKind::Templates));
+        assert_eq!(kind("/site/static/css/site.css"), Some(ChangeKind::StaticFiles));
+        assert_eq!(kind("/site/sass/site.scss"), Some(ChangeKind::Sass));
+        assert_eq!(kind("/site/themes/corp/templates/base.html"), Some(ChangeKind::Templates));
+        assert_eq!(kind("/site/themes/corp/static/logo.svg"), Some(ChangeKind::StaticFiles));
+        assert_eq!(kind("/site/themes/corp/sass/_colors.scss"), Some(ChangeKind::Sass));
+        assert_eq!(kind("/site/themes/corp/theme.toml"), Some(ChangeKind::Config));
+        assert_eq!(kind("/site/themes/corp/README.md"), None);
+        assert_eq!(kind("/site/content/blog/.post.md.swp"), None);
+        assert_eq!(kind("/site/content/blog/post.md~"), None);
+        assert_eq!(kind("/site/public/index.html"), None);
+        assert_eq!(kind("/site/README.md"), None);
+    }
+
+    #[test]
+    fn test_template_change_keeps_content_changes() {
+        let root = Path::new("/site");
+        let config = Path::new("/site/config.toml");
+        let event = |path: &str| {
+            let event = Event::new(EventKind::Modify(ModifyKind::Any)).add_path(PathBuf::from(path));
+            DebouncedEvent::new(event, Instant::now())
+        };
+        let events = [
+            event("/site/content/blog/post.md"),
+            event("/site/sass/site.scss"),
+            event("/site/templates/page.html"),
+            event("/site/static/logo.svg"),
+        ];
+
+        assert_eq!(
+            changes(&events, root, config),
+            vec![
+                (ChangeKind::Templates, PathBuf::from("/site/templates/page.html")),
+                (ChangeKind::Content, PathBuf::from("/site/content/blog/post.md")),
+                (ChangeKind::StaticFiles, PathBuf::from("/site/static/logo.svg")),
+            ]
+        );
+    }
 }
\ No newline at end of file